@@ -0,0 +1,64 @@
+//! Lock and unlock `SpinLock` from several threads at once with each backoff strategy.
+//!
+//! Each iteration is one critical section,
+//! and the iterations are divided among the threads.
+//!
+//! `Backoff::Mwait` uses Monitor/MWAIT only with the `x86_mwait` feature.
+//! Otherwise, the "mwait" case only spins on the lock word.
+
+use awkernel_sync::spinlock::{Backoff, SpinLock};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::{
+    hint::black_box,
+    sync::{Arc, Barrier},
+    thread,
+    time::{Duration, Instant},
+};
+
+const BACKOFFS: [(&str, Backoff); 3] = [
+    ("none", Backoff::None),
+    ("exponential", Backoff::Exponential { cap: 64 }),
+    ("mwait", Backoff::Mwait),
+];
+
+fn contended(iters: u64, num_threads: u64, backoff: Backoff) -> Duration {
+    let lock = Arc::new(SpinLock::new(0u64).with_backoff(backoff));
+    let barrier = Arc::new(Barrier::new(num_threads as usize + 1));
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let lock = lock.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..iters / num_threads {
+                    *lock.lock() += black_box(1);
+                }
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let start = Instant::now();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn bench_backoff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spinlock");
+
+    for num_threads in [1, 2, 4] {
+        for (name, backoff) in BACKOFFS {
+            group.bench_with_input(BenchmarkId::new(name, num_threads), &num_threads, |b, n| {
+                b.iter_custom(|iters| contended(iters, *n, backoff))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_backoff);
+criterion_main!(benches);
@@ -0,0 +1,101 @@
+//! Accesses to the data protected by a lock, held by the guards.
+//!
+//! Under loom, a guard holds a pointer tracked by loom from acquisition to release,
+//! so that `Deref` and `DerefMut` of the guard are checked for data races.
+//! Otherwise, these are zero-sized and the pointer is taken from the `UnsafeCell` directly.
+
+use core::marker::PhantomData;
+
+#[cfg(not(loom))]
+use core::cell::UnsafeCell;
+
+#[cfg(loom)]
+use loom::cell::UnsafeCell;
+
+/// Read access by a reader guard.
+pub(crate) struct ReadAccess<T> {
+    #[cfg(loom)]
+    ptr: Option<loom::cell::ConstPtr<T>>,
+
+    _phantom: PhantomData<*const T>,
+}
+
+impl<T> ReadAccess<T> {
+    /// Start reading. The caller must hold the lock.
+    #[inline(always)]
+    pub(crate) fn start(_cell: &UnsafeCell<T>) -> Self {
+        ReadAccess {
+            #[cfg(loom)]
+            ptr: Some(_cell.get()),
+            _phantom: PhantomData,
+        }
+    }
+
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub(crate) fn get(&self, cell: &UnsafeCell<T>) -> *const T {
+        cell.get()
+    }
+
+    #[cfg(loom)]
+    #[inline(always)]
+    pub(crate) fn get(&self, _cell: &UnsafeCell<T>) -> *const T {
+        self.ptr.as_ref().unwrap().with(|ptr| ptr)
+    }
+
+    /// Stop reading. This must be called before the lock is released.
+    #[inline(always)]
+    pub(crate) fn end(&mut self) {
+        #[cfg(loom)]
+        self.ptr.take();
+    }
+}
+
+/// Write access by a writer guard.
+pub(crate) struct WriteAccess<T> {
+    #[cfg(loom)]
+    ptr: Option<loom::cell::MutPtr<T>>,
+
+    _phantom: PhantomData<*mut T>,
+}
+
+impl<T> WriteAccess<T> {
+    /// Start writing. The caller must hold the lock exclusively.
+    #[inline(always)]
+    pub(crate) fn start(_cell: &UnsafeCell<T>) -> Self {
+        WriteAccess {
+            #[cfg(loom)]
+            ptr: Some(_cell.get_mut()),
+            _phantom: PhantomData,
+        }
+    }
+
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub(crate) fn get(&self, cell: &UnsafeCell<T>) -> *mut T {
+        cell.get()
+    }
+
+    #[cfg(loom)]
+    #[inline(always)]
+    pub(crate) fn get(&self, _cell: &UnsafeCell<T>) -> *mut T {
+        self.ptr.as_ref().unwrap().with(|ptr| ptr)
+    }
+
+    /// No access yet, for a guard made before the lock is acquired.
+    #[inline(always)]
+    pub(crate) fn idle() -> Self {
+        WriteAccess {
+            #[cfg(loom)]
+            ptr: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Stop writing. This must be called before the lock is released.
+    #[inline(always)]
+    pub(crate) fn end(&mut self) {
+        #[cfg(loom)]
+        self.ptr.take();
+    }
+}
@@ -0,0 +1,299 @@
+//! # Big reader lock
+//!
+//! `BrLock` is a reader-writer lock for read-mostly data.
+//! Each CPU has its own reader count on a separate cache line,
+//! so readers on different CPUs do not share any cache line.
+//! Instead, a writer has to visit the reader counts of all CPUs.
+//!
+//! The reader counts are allocated when the lock is created,
+//! and their number is given by the function registered by `crate::set_num_cpus_fn`.
+//!
+//! A CPU holding the reader lock can acquire it again even while a writer is waiting.
+//!
+//! `BrLock` supports `read`, `write`, `try_read`, `try_write`, `with_read`, `with_write`
+//! and `BrLockWriteGuard::downgrade` of `RwLock`.
+//! Mapped guards, upgradable readers, `Arc` guards and timed acquisition are not supported.
+//!
+//! ```
+//! use awkernel_sync::brlock::BrLock;
+//!
+//! let lock = BrLock::new(0);
+//!
+//! *lock.write() += 1;
+//!
+//! let r1 = lock.read();
+//! let r2 = lock.read();
+//! assert_eq!(*r1 + *r2, 2);
+//! assert!(lock.try_write().is_none());
+//! ```
+
+use alloc::boxed::Box;
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The writer flag.
+const WRITE_LOCKED: usize = 1;
+
+#[repr(align(64))]
+struct ReaderCount(AtomicUsize);
+
+pub struct BrLock<T: Send> {
+    readers: Box<[ReaderCount]>,
+
+    /// `WRITE_LOCKED` while a writer holds or is acquiring the lock.
+    writer: AtomicUsize,
+
+    data: UnsafeCell<T>,
+}
+
+impl<T: Send> BrLock<T> {
+    pub fn new(v: T) -> Self {
+        let readers = (0..crate::num_cpus())
+            .map(|_| ReaderCount(AtomicUsize::new(0)))
+            .collect();
+
+        Self {
+            readers,
+            writer: AtomicUsize::new(0),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    /// The reader count of the current CPU.
+    /// Interrupts must be disabled so that the CPU does not change.
+    #[inline(always)]
+    fn reader_count(&self) -> (usize, &AtomicUsize) {
+        let cpu = crate::cpu_id();
+        let Some(count) = self.readers.get(cpu) else {
+            panic!(
+                "CPU {cpu} is out of the {} CPUs of BrLock",
+                self.readers.len()
+            );
+        };
+
+        (cpu, &count.0)
+    }
+
+    /// Count the reader on the current CPU if no writer is present.
+    #[inline(always)]
+    fn try_lock_shared(&self, count: &AtomicUsize) -> bool {
+        // this CPU already holds the reader lock,
+        // and a writer cannot pass the count until it is released
+        if count.fetch_add(1, Ordering::SeqCst) > 0 {
+            return true;
+        }
+
+        // a writer sets the flag and then reads the counts,
+        // so either the writer sees this count or this sees the flag
+        if self.writer.load(Ordering::SeqCst) == 0 {
+            return true;
+        }
+
+        count.fetch_sub(1, Ordering::Release);
+        false
+    }
+
+    /// acquire reader lock
+    #[inline(always)]
+    pub fn read(&self) -> BrLockReadGuard<'_, T> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        let (cpu, count) = self.reader_count();
+
+        while !self.try_lock_shared(count) {
+            crate::mwait::wait_while_equal(&self.writer, WRITE_LOCKED, Ordering::Relaxed);
+        }
+
+        BrLockReadGuard {
+            brlock: self,
+            cpu,
+            _interrupt_guard,
+            _phantom: Default::default(),
+        }
+    }
+
+    /// Call `f` with the data while holding the reader lock.
+    #[inline(always)]
+    pub fn with_read<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        f(&self.read())
+    }
+
+    /// Call `f` with the data while holding the writer lock.
+    #[inline(always)]
+    pub fn with_write<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        f(&mut self.write())
+    }
+
+    /// Try to acquire the reader lock.
+    ///
+    /// Return `None` if the lock is held or waited for by a writer,
+    /// unless the current CPU already holds the reader lock.
+    #[inline(always)]
+    pub fn try_read(&self) -> Option<BrLockReadGuard<'_, T>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        let (cpu, count) = self.reader_count();
+
+        if self.try_lock_shared(count) {
+            Some(BrLockReadGuard {
+                brlock: self,
+                cpu,
+                _interrupt_guard,
+                _phantom: Default::default(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// acquire writer lock
+    #[inline(always)]
+    pub fn write(&self) -> BrLockWriteGuard<'_, T> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+
+        while self
+            .writer
+            .compare_exchange_weak(0, WRITE_LOCKED, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            crate::mwait::wait_while_equal(&self.writer, WRITE_LOCKED, Ordering::Relaxed);
+        }
+
+        // new readers wait for the flag, so wait only for the present readers
+        for count in self.readers.iter() {
+            let mut c = count.0.load(Ordering::SeqCst);
+            while c != 0 {
+                crate::mwait::wait_while_equal(&count.0, c, Ordering::Relaxed);
+                c = count.0.load(Ordering::SeqCst);
+            }
+        }
+
+        BrLockWriteGuard {
+            brlock: self,
+            _interrupt_guard,
+            _phantom: Default::default(),
+        }
+    }
+
+    /// Try to acquire the writer lock.
+    ///
+    /// Return `None` if the lock is held by readers or a writer.
+    #[inline(always)]
+    pub fn try_write(&self) -> Option<BrLockWriteGuard<'_, T>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+
+        if self
+            .writer
+            .compare_exchange(0, WRITE_LOCKED, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+
+        if self
+            .readers
+            .iter()
+            .any(|count| count.0.load(Ordering::SeqCst) != 0)
+        {
+            self.writer.store(0, Ordering::Release);
+            return None;
+        }
+
+        Some(BrLockWriteGuard {
+            brlock: self,
+            _interrupt_guard,
+            _phantom: Default::default(),
+        })
+    }
+}
+
+unsafe impl<T: Send> Sync for BrLock<T> {}
+unsafe impl<T: Send> Send for BrLock<T> {}
+
+pub struct BrLockReadGuard<'a, T: Send> {
+    brlock: &'a BrLock<T>,
+
+    /// The CPU whose reader count has been incremented.
+    cpu: usize,
+
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<T: Send> Drop for BrLockReadGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.brlock.readers[self.cpu]
+            .0
+            .fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<T: Send> Deref for BrLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.brlock.data.get() }
+    }
+}
+
+pub struct BrLockWriteGuard<'a, T: Send> {
+    brlock: &'a BrLock<T>,
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<'a, T: Send> BrLockWriteGuard<'a, T> {
+    /// Convert to the reader lock without releasing the lock.
+    ///
+    /// No writer can acquire the lock between them,
+    /// so the caller reads what it wrote.
+    /// This is an associated function to avoid conflicts with methods of `T`.
+    #[inline(always)]
+    pub fn downgrade(guard: Self) -> BrLockReadGuard<'a, T> {
+        let guard = ManuallyDrop::new(guard);
+        let _interrupt_guard = unsafe { core::ptr::read(&guard._interrupt_guard) };
+
+        // count myself as a reader before letting other readers in
+        let (cpu, count) = guard.brlock.reader_count();
+        count.fetch_add(1, Ordering::SeqCst);
+        guard.brlock.writer.store(0, Ordering::Release);
+
+        BrLockReadGuard {
+            brlock: guard.brlock,
+            cpu,
+            _interrupt_guard,
+            _phantom: Default::default(),
+        }
+    }
+}
+
+impl<T: Send> Drop for BrLockWriteGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.brlock.writer.store(0, Ordering::Release);
+    }
+}
+
+impl<T: Send> Deref for BrLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.brlock.data.get() }
+    }
+}
+
+impl<T: Send> DerefMut for BrLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.brlock.data.get() }
+    }
+}
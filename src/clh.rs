@@ -0,0 +1,316 @@
+//! # CLH lock
+//!
+//! `CLHLock` is a fair queue lock like `MCSLock`,
+//! but a waiter spins on the cell of its predecessor instead of its own cell.
+//! Because the releaser does not need to know its successor,
+//! releasing the lock never waits.
+//!
+//! The cell of a released lock is handed over to the successor,
+//! and the releaser takes over the cell of its predecessor in return.
+//! Cells are therefore allocated on the heap and owned by `CLHNode`.
+//! A `CLHNode` allocates a cell when it is used for the first time,
+//! and frees a cell left over by a previous acquisition when it is used again.
+//! `lock` and `try_lock` do both before interrupts are disabled,
+//! so the allocator is never called while the lock is waited for or held.
+//!
+//! ```
+//! use awkernel_sync::clh::{CLHLock, CLHNode};
+//!
+//! let lock = CLHLock::new(0);
+//!
+//! let mut node = CLHNode::new();
+//! *lock.lock(&mut node) += 1;
+//!
+//! let mut node = CLHNode::new();
+//! assert_eq!(*lock.try_lock(&mut node).unwrap(), 1);
+//! ```
+
+use alloc::boxed::Box;
+use core::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::null_mut,
+};
+
+#[cfg(not(loom))]
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, Ordering},
+};
+
+#[cfg(loom)]
+use loom::{
+    cell::UnsafeCell,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, Ordering},
+};
+
+use crate::access::WriteAccess;
+
+pub struct CLHLock<T: Send> {
+    /// The cell of the last waiter, or null if the lock is free.
+    tail: AtomicPtr<Cell>,
+
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for CLHLock<T> {}
+unsafe impl<T: Send> Send for CLHLock<T> {}
+
+/// A cell waited for by the successor.
+struct Cell {
+    locked: AtomicBool,
+}
+
+/// A queue node of `CLHLock`.
+///
+/// A node does not depend on the type of the protected data,
+/// so it can be reused for locks of different types.
+pub struct CLHNode {
+    /// The cell enqueued by the next acquisition.
+    cell: *mut Cell,
+
+    /// The cell taken over from a predecessor, kept for a later acquisition.
+    spare: *mut Cell,
+}
+
+unsafe impl Send for CLHNode {}
+
+impl Default for CLHNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CLHNode {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        CLHNode {
+            cell: null_mut(),
+            spare: null_mut(),
+        }
+    }
+
+    /// Make sure that the node has a cell to enqueue and no spare cell, and return the cell.
+    #[inline(always)]
+    fn prepare(&mut self) -> *mut Cell {
+        if self.cell.is_null() {
+            self.cell = if self.spare.is_null() {
+                Box::into_raw(Box::new(Cell {
+                    locked: AtomicBool::new(false),
+                }))
+            } else {
+                core::mem::replace(&mut self.spare, null_mut())
+            };
+        } else if !self.spare.is_null() {
+            // the lock was released without a successor, so the spare is left over
+            drop(unsafe { Box::from_raw(core::mem::replace(&mut self.spare, null_mut())) });
+        }
+
+        self.cell
+    }
+
+    /// Take over the cell released by the predecessor.
+    /// `prepare` must be called before this.
+    #[inline(always)]
+    fn take_over(&mut self, pred: *mut Cell) {
+        debug_assert!(self.spare.is_null());
+        self.spare = pred;
+    }
+}
+
+impl Drop for CLHNode {
+    fn drop(&mut self) {
+        for cell in [self.cell, self.spare] {
+            if !cell.is_null() {
+                drop(unsafe { Box::from_raw(cell) });
+            }
+        }
+    }
+}
+
+impl<T: Send> CLHLock<T> {
+    #[cfg(not(loom))]
+    pub const fn new(v: T) -> Self {
+        CLHLock {
+            tail: AtomicPtr::new(null_mut()),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new(v: T) -> Self {
+        CLHLock {
+            tail: AtomicPtr::new(null_mut()),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    #[inline(always)]
+    pub fn try_lock<'a>(&'a self, node: &'a mut CLHNode) -> Option<CLHLockGuard<'a, T>> {
+        node.prepare();
+
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        if unsafe { self.try_lock_raw(node) } {
+            Some(CLHLockGuard::new(self, node, _interrupt_guard))
+        } else {
+            None
+        }
+    }
+
+    /// acquire lock
+    #[inline(always)]
+    pub fn lock<'a>(&'a self, node: &'a mut CLHNode) -> CLHLockGuard<'a, T> {
+        node.prepare();
+
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        unsafe { self.lock_raw(node) };
+        CLHLockGuard::new(self, node, _interrupt_guard)
+    }
+
+    /// Try to acquire the lock with `node` without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// If this returns `true`, `node` must not be dropped
+    /// until `unlock_raw` is called with it.
+    /// Interrupts should be disabled while the lock is held.
+    /// Unlike `try_lock`, this may allocate or free a cell of `node` after they are disabled.
+    #[inline(always)]
+    pub unsafe fn try_lock_raw(&self, node: &mut CLHNode) -> bool {
+        let cell = node.prepare();
+        (*cell).locked.store(true, Ordering::Relaxed);
+
+        self.tail
+            .compare_exchange(null_mut(), cell, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Acquire the lock with `node` without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// `node` must not be dropped until `unlock_raw` is called with it.
+    /// Interrupts should be disabled while the lock is held or waited for.
+    /// Unlike `lock`, this may allocate or free a cell of `node` after they are disabled.
+    #[inline(always)]
+    pub unsafe fn lock_raw(&self, node: &mut CLHNode) {
+        let cell = node.prepare();
+        (*cell).locked.store(true, Ordering::Relaxed);
+
+        // set my cell as the tail
+        let pred = self.tail.swap(cell, Ordering::AcqRel);
+        if pred.is_null() {
+            return;
+        }
+
+        // spin until the predecessor releases the lock
+        super::mwait::wait_while_true(&(*pred).locked);
+
+        fence(Ordering::Acquire);
+
+        node.take_over(pred);
+    }
+
+    /// Release the lock acquired by `lock_raw` or `try_lock_raw`.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held with `node`.
+    #[inline(always)]
+    pub unsafe fn unlock_raw(&self, node: &mut CLHNode) {
+        let cell = node.cell;
+
+        // keep my cell if there is no successor
+        if self
+            .tail
+            .compare_exchange(cell, null_mut(), Ordering::Release, Ordering::Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+
+        // hand my cell over to the successor
+        (*cell).locked.store(false, Ordering::Release);
+        node.cell = core::mem::replace(&mut node.spare, null_mut());
+    }
+
+    /// Return a pointer to the protected data.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+pub struct CLHLockGuard<'a, T: Send> {
+    node: &'a mut CLHNode,
+    clh_lock: &'a CLHLock<T>,
+    access: WriteAccess<T>,
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<'a, T: Send> CLHLockGuard<'a, T> {
+    #[inline(always)]
+    fn new(
+        clh_lock: &'a CLHLock<T>,
+        node: &'a mut CLHNode,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        CLHLockGuard {
+            node,
+            clh_lock,
+            access: WriteAccess::start(&clh_lock.data),
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Send> CLHLockGuard<'_, T> {
+    #[cfg(loom)]
+    pub fn with_mut<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(*mut T) -> R,
+    {
+        f(self.access.get(&self.clh_lock.data))
+    }
+}
+
+impl<T: Send> Drop for CLHLockGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.access.end();
+        unsafe { self.clh_lock.unlock_raw(self.node) };
+    }
+}
+
+impl<T: Send> Deref for CLHLockGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.access.get(&self.clh_lock.data) }
+    }
+}
+
+impl<T: Send> DerefMut for CLHLockGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.access.get(&self.clh_lock.data) }
+    }
+}
+
+impl<T: Send> AsMut<T> for CLHLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.access.get(&self.clh_lock.data) }
+    }
+}
+
+impl<T: Send> AsRef<T> for CLHLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &T {
+        unsafe { &*self.access.get(&self.clh_lock.data) }
+    }
+}
@@ -0,0 +1,285 @@
+//! # Cohort lock
+//!
+//! `CohortLock` is a C-BO-MCS lock for machines with clusters of CPUs,
+//! such as NUMA nodes or big.LITTLE clusters.
+//! Each cluster has a local `MCSLock`, and the clusters compete for a global `SpinLock` with exponential backoff.
+//!
+//! The holder passes the lock to a waiter of the same cluster without releasing the global lock,
+//! so that the protected data stays in the caches of the cluster.
+//! To bound the unfairness to the other clusters,
+//! the global lock is released after `max_handoffs` consecutive handoffs within a cluster.
+//!
+//! The cluster of a CPU is given by the function registered by `crate::set_cluster_of_fn`.
+//! Because the lock is released through the cluster of the current CPU,
+//! it must be released on the CPU that acquired it,
+//! and `CohortLock<()>` does not implement `RawLockSend` for `Mutex::lock_arc`.
+//!
+//! ```
+//! use awkernel_sync::{cohort::CohortLock, mcs::MCSNode};
+//!
+//! let lock = CohortLock::new(0).with_max_handoffs(16);
+//!
+//! let mut node = MCSNode::new();
+//! *lock.lock(&mut node) += 1;
+//!
+//! let mut node = MCSNode::new();
+//! assert_eq!(*lock.try_lock(&mut node).unwrap(), 1);
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use crate::{
+    access::WriteAccess,
+    mcs::{MCSLock, MCSNode},
+    mutex::RawLock,
+    spinlock::{Backoff, SpinLock},
+};
+
+/// The maximum number of clusters.
+pub const NUM_MAX_CLUSTERS: usize = 8;
+
+/// The default number of consecutive handoffs within a cluster.
+pub const DEFAULT_MAX_HANDOFFS: u32 = 64;
+
+/// The backoff of the global lock.
+const GLOBAL_BACKOFF: Backoff = Backoff::Exponential { cap: 1024 };
+
+#[repr(align(64))]
+struct Cluster {
+    local: MCSLock<()>,
+
+    /// Set when the global lock is passed with the local lock.
+    /// This is accessed only by the holder of the local lock.
+    global_held: AtomicBool,
+
+    /// The number of consecutive handoffs within the cluster.
+    handoffs: AtomicU32,
+}
+
+impl Cluster {
+    const fn new() -> Self {
+        Cluster {
+            local: MCSLock::new(()),
+            global_held: AtomicBool::new(false),
+            handoffs: AtomicU32::new(0),
+        }
+    }
+}
+
+pub struct CohortLock<T> {
+    global: SpinLock<()>,
+    clusters: [Cluster; NUM_MAX_CLUSTERS],
+    max_handoffs: u32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for CohortLock<T> {}
+unsafe impl<T: Send> Send for CohortLock<T> {}
+
+impl<T> CohortLock<T> {
+    pub const fn new(v: T) -> Self {
+        CohortLock {
+            global: SpinLock::new(()).with_backoff(GLOBAL_BACKOFF),
+            clusters: [const { Cluster::new() }; NUM_MAX_CLUSTERS],
+            max_handoffs: DEFAULT_MAX_HANDOFFS,
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    /// Set how many times the lock can be passed within a cluster
+    /// while waiters of other clusters may be waiting.
+    ///
+    /// With 0, the global lock is released every time like a plain C-BO-MCS lock without cohorting.
+    #[inline(always)]
+    pub const fn with_max_handoffs(mut self, max_handoffs: u32) -> Self {
+        self.max_handoffs = max_handoffs;
+        self
+    }
+
+    #[inline(always)]
+    pub fn try_lock<'a>(&'a self, node: &'a mut MCSNode) -> Option<CohortLockGuard<'a, T>> {
+        // interrupts must be disabled before looking up the cluster of the current CPU
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        if unsafe { self.try_lock_raw(node) } {
+            Some(CohortLockGuard::new(self, node, _interrupt_guard))
+        } else {
+            None
+        }
+    }
+
+    /// acquire lock
+    #[inline(always)]
+    pub fn lock<'a>(&'a self, node: &'a mut MCSNode) -> CohortLockGuard<'a, T> {
+        // interrupts must be disabled before looking up the cluster of the current CPU
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        unsafe { self.lock_raw(node) };
+        CohortLockGuard::new(self, node, _interrupt_guard)
+    }
+
+    /// Try to acquire the lock with `node` without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// If this returns `true`, `node` must not be moved or dropped
+    /// until `unlock_raw` is called with it on the same CPU.
+    /// Interrupts should be disabled while the lock is held.
+    #[inline(always)]
+    pub unsafe fn try_lock_raw(&self, node: &mut MCSNode) -> bool {
+        let cluster = self.cluster();
+        if !cluster.local.try_lock_raw(node) {
+            return false;
+        }
+
+        // no one passes the global lock to an empty queue
+        if self.global.try_lock_raw() {
+            cluster.handoffs.store(0, Ordering::Relaxed);
+            true
+        } else {
+            cluster.local.unlock_raw(node);
+            false
+        }
+    }
+
+    /// Acquire the lock with `node` without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// `node` must not be moved or dropped until `unlock_raw` is called with it on the same CPU.
+    /// Interrupts should be disabled while the lock is held or waited for.
+    #[inline(always)]
+    pub unsafe fn lock_raw(&self, node: &mut MCSNode) {
+        let cluster = self.cluster();
+        cluster.local.lock_raw(node);
+
+        if cluster.global_held.load(Ordering::Relaxed) {
+            // the global lock is passed by the predecessor in the cluster
+            return;
+        }
+
+        self.global.lock_raw();
+        cluster.handoffs.store(0, Ordering::Relaxed);
+    }
+
+    /// Release the lock acquired by `lock_raw` or `try_lock_raw`.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held with `node` by the current CPU.
+    #[inline(always)]
+    pub unsafe fn unlock_raw(&self, node: &MCSNode) {
+        let cluster = self.cluster();
+
+        let handoffs = cluster.handoffs.load(Ordering::Relaxed);
+        if handoffs < self.max_handoffs && cluster.local.has_successor(node) {
+            // pass the global lock to the successor in the cluster
+            cluster.handoffs.store(handoffs + 1, Ordering::Relaxed);
+            cluster.global_held.store(true, Ordering::Relaxed);
+        } else {
+            cluster.global_held.store(false, Ordering::Relaxed);
+            self.global.unlock_raw();
+        }
+
+        cluster.local.unlock_raw(node);
+    }
+
+    /// Return a pointer to the protected data.
+    #[inline(always)]
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    #[inline(always)]
+    fn cluster(&self) -> &Cluster {
+        let cluster = crate::cluster_of(crate::cpu_id());
+        &self.clusters[cluster]
+    }
+}
+
+unsafe impl RawLock for CohortLock<()> {
+    const INIT: Self = CohortLock::new(());
+
+    const USES_NODE: bool = true;
+
+    #[inline(always)]
+    unsafe fn lock(&self, node: &mut MCSNode) {
+        self.lock_raw(node);
+    }
+
+    #[inline(always)]
+    unsafe fn try_lock(&self, node: &mut MCSNode) -> bool {
+        self.try_lock_raw(node)
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self, node: &MCSNode) {
+        self.unlock_raw(node);
+    }
+}
+
+pub struct CohortLockGuard<'a, T> {
+    node: &'a mut MCSNode,
+    cohort_lock: &'a CohortLock<T>,
+    access: WriteAccess<T>,
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<'a, T> CohortLockGuard<'a, T> {
+    #[inline(always)]
+    fn new(
+        cohort_lock: &'a CohortLock<T>,
+        node: &'a mut MCSNode,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        CohortLockGuard {
+            node,
+            cohort_lock,
+            access: WriteAccess::start(&cohort_lock.data),
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for CohortLockGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.access.end();
+        unsafe { self.cohort_lock.unlock_raw(self.node) };
+    }
+}
+
+impl<T: Send> Deref for CohortLockGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.access.get(&self.cohort_lock.data) }
+    }
+}
+
+impl<T: Send> DerefMut for CohortLockGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.access.get(&self.cohort_lock.data) }
+    }
+}
+
+impl<T: Send> AsMut<T> for CohortLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.access.get(&self.cohort_lock.data) }
+    }
+}
+
+impl<T: Send> AsRef<T> for CohortLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &T {
+        unsafe { &*self.access.get(&self.cohort_lock.data) }
+    }
+}
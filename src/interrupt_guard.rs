@@ -30,7 +30,7 @@ pub use crate::interrupt_guard::rv32::*;
 
 /// Disable interrupts and automatically restored the configuration.
 ///
-/// ```
+/// ```ignore
 /// {
 ///     use awkernel_lib::interrupt::InterruptGuard;
 ///
@@ -67,3 +67,65 @@ impl Drop for InterruptGuard {
         }
     }
 }
+
+/// The interrupt configuration saved by the outermost `disable_nested` on a CPU.
+#[cfg(all(feature = "lock_api", not(loom)))]
+#[repr(align(64))]
+struct NestedFlag {
+    depth: core::sync::atomic::AtomicUsize,
+    flag: core::sync::atomic::AtomicUsize,
+}
+
+#[cfg(all(feature = "lock_api", not(loom)))]
+static NESTED_FLAGS: [NestedFlag; crate::NUM_MAX_CPU] = [const {
+    NestedFlag {
+        depth: core::sync::atomic::AtomicUsize::new(0),
+        flag: core::sync::atomic::AtomicUsize::new(0),
+    }
+}; crate::NUM_MAX_CPU];
+
+/// Disable interrupts without a guard, for raw locks released by a separate call.
+///
+/// Calls can be nested on a CPU, like `push_off` of xv6.
+/// The configuration is restored by the `restore_nested` matching the outermost call.
+#[cfg(all(feature = "lock_api", not(loom)))]
+#[inline(always)]
+pub(crate) fn disable_nested() {
+    use core::sync::atomic::Ordering;
+
+    let flag = get_flag();
+    disable();
+
+    // only this CPU touches its entry, with interrupts disabled
+    let nested = &NESTED_FLAGS[crate::cpu_id()];
+    let depth = nested.depth.load(Ordering::Relaxed);
+    if depth == 0 {
+        nested.flag.store(flag, Ordering::Relaxed);
+    }
+    nested.depth.store(depth + 1, Ordering::Relaxed);
+}
+
+/// Undo `disable_nested`.
+///
+/// # Safety
+///
+/// This must be called on the CPU that called `disable_nested`,
+/// and interrupts must not be enabled in between.
+#[cfg(all(feature = "lock_api", not(loom)))]
+#[inline(always)]
+pub(crate) unsafe fn restore_nested() {
+    use core::sync::atomic::Ordering;
+
+    let nested = &NESTED_FLAGS[crate::cpu_id()];
+    let depth = nested.depth.load(Ordering::Relaxed) - 1;
+    nested.depth.store(depth, Ordering::Relaxed);
+    if depth != 0 {
+        return;
+    }
+
+    set_flag(nested.flag.load(Ordering::Relaxed));
+
+    if are_enabled() {
+        super::voluntary_preemption();
+    }
+}
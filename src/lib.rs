@@ -4,12 +4,32 @@ use core::sync::atomic::{AtomicPtr, Ordering};
 
 extern crate alloc;
 
+mod access;
+#[cfg(not(loom))]
+pub mod brlock;
+pub mod clh;
+#[cfg(not(loom))]
+pub mod cohort;
 mod interrupt_guard;
+#[cfg(all(feature = "lock_api", not(loom)))]
+pub mod lock_api;
 pub mod mcs;
+pub mod mcs_rw;
+#[cfg(not(loom))]
+pub mod mcs_tp;
 pub mod mutex;
 mod mwait;
+#[cfg(not(loom))]
+pub mod poison;
+#[cfg(not(loom))]
+pub mod qspinlock;
 pub mod rwlock;
+pub mod semaphore;
 pub mod spinlock;
+pub mod ticket;
+
+/// The maximum number of CPUs supported by per-CPU data structures.
+pub const NUM_MAX_CPU: usize = 512;
 
 static VOLUNTARY_PREEMPT_FN: AtomicPtr<()> = AtomicPtr::new(empty as *mut ());
 
@@ -26,3 +46,190 @@ pub fn set_voluntary_preemption_fn(f: unsafe fn()) {
     let ptr = f as *const () as *mut ();
     VOLUNTARY_PREEMPT_FN.store(ptr, Ordering::Relaxed);
 }
+
+static UPTIME_FN: AtomicPtr<()> = AtomicPtr::new(default_uptime as *mut ());
+
+#[cfg(not(feature = "std"))]
+fn default_uptime() -> u64 {
+    0
+}
+
+#[cfg(feature = "std")]
+fn default_uptime() -> u64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_micros() as u64
+}
+
+/// Return the uptime in microseconds by calling the function registered by `set_uptime_fn`.
+#[inline(always)]
+pub(crate) fn uptime() -> u64 {
+    let uptime = UPTIME_FN.load(Ordering::Relaxed);
+    let uptime = unsafe { core::mem::transmute::<*mut (), fn() -> u64>(uptime) };
+    uptime()
+}
+
+/// Set the clock used by timed lock acquisitions such as `MCSLock::lock_timeout`.
+///
+/// `f` must return a monotonic uptime in microseconds.
+/// Without `std`, the default clock always returns 0,
+/// so deadlines never expire until a clock is registered.
+pub fn set_uptime_fn(f: fn() -> u64) {
+    let ptr = f as *const () as *mut ();
+    UPTIME_FN.store(ptr, Ordering::Relaxed);
+}
+
+static CPU_ID_FN: AtomicPtr<()> = AtomicPtr::new(default_cpu_id as *mut ());
+
+#[cfg(not(feature = "std"))]
+fn default_cpu_id() -> usize {
+    0
+}
+
+/// Without a kernel, every thread is regarded as a CPU.
+/// IDs are assigned when a thread uses them first and released when the thread exits.
+#[cfg(feature = "std")]
+fn default_cpu_id() -> usize {
+    use core::sync::atomic::AtomicBool;
+
+    static USED: [AtomicBool; NUM_MAX_CPU] = [const { AtomicBool::new(false) }; NUM_MAX_CPU];
+
+    struct ThreadCpuId(usize);
+
+    impl ThreadCpuId {
+        fn new() -> Self {
+            for (i, used) in USED.iter().enumerate() {
+                if used
+                    .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return ThreadCpuId(i);
+                }
+            }
+
+            panic!("more than NUM_MAX_CPU threads use per-CPU data");
+        }
+    }
+
+    impl Drop for ThreadCpuId {
+        fn drop(&mut self) {
+            USED[self.0].store(false, Ordering::Relaxed);
+        }
+    }
+
+    std::thread_local! {
+        static CPU_ID: ThreadCpuId = ThreadCpuId::new();
+    }
+
+    CPU_ID.with(|id| id.0)
+}
+
+/// Return the ID of the current CPU by calling the function registered by `set_cpu_id_fn`.
+#[cfg(not(loom))]
+#[inline(always)]
+pub(crate) fn cpu_id() -> usize {
+    let cpu_id = CPU_ID_FN.load(Ordering::Relaxed);
+    let cpu_id = unsafe { core::mem::transmute::<*mut (), fn() -> usize>(cpu_id) };
+    cpu_id()
+}
+
+/// Set the function returning the ID of the current CPU.
+///
+/// `f` must return a value less than `NUM_MAX_CPU`.
+/// It is called with interrupts disabled, so the result does not change while per-CPU data is used.
+/// Without `std`, the default function always returns 0,
+/// so it must be registered before per-CPU data is used on multiple CPUs.
+pub fn set_cpu_id_fn(f: fn() -> usize) {
+    let ptr = f as *const () as *mut ();
+    CPU_ID_FN.store(ptr, Ordering::Relaxed);
+}
+
+static NUM_CPUS_FN: AtomicPtr<()> = AtomicPtr::new(default_num_cpus as *mut ());
+
+#[cfg(not(feature = "std"))]
+fn default_num_cpus() -> usize {
+    1
+}
+
+/// Every thread is regarded as a CPU, so all IDs can be used.
+#[cfg(feature = "std")]
+fn default_num_cpus() -> usize {
+    NUM_MAX_CPU
+}
+
+/// Return the number of CPUs by calling the function registered by `set_num_cpus_fn`.
+#[cfg(not(loom))]
+#[inline(always)]
+pub(crate) fn num_cpus() -> usize {
+    let num_cpus = NUM_CPUS_FN.load(Ordering::Relaxed);
+    let num_cpus = unsafe { core::mem::transmute::<*mut (), fn() -> usize>(num_cpus) };
+    num_cpus()
+}
+
+/// Set the function returning the number of CPUs.
+///
+/// `f` must return a value greater than every ID returned by the function registered by `set_cpu_id_fn`.
+/// Per-CPU data such as `brlock::BrLock` is sized by it when created,
+/// so it must be registered before such data is created.
+pub fn set_num_cpus_fn(f: fn() -> usize) {
+    let ptr = f as *const () as *mut ();
+    NUM_CPUS_FN.store(ptr, Ordering::Relaxed);
+}
+
+static CLUSTER_OF_FN: AtomicPtr<()> = AtomicPtr::new(default_cluster_of as *mut ());
+
+fn default_cluster_of(_cpu: usize) -> usize {
+    0
+}
+
+/// Return the cluster of `cpu` by calling the function registered by `set_cluster_of_fn`.
+#[cfg(not(loom))]
+#[inline(always)]
+pub(crate) fn cluster_of(cpu: usize) -> usize {
+    let cluster_of = CLUSTER_OF_FN.load(Ordering::Relaxed);
+    let cluster_of = unsafe { core::mem::transmute::<*mut (), fn(usize) -> usize>(cluster_of) };
+    cluster_of(cpu)
+}
+
+/// Set the function returning the cluster, such as a NUMA node or a big.LITTLE cluster, of a CPU.
+///
+/// `f` must return a value less than `cohort::NUM_MAX_CLUSTERS`.
+/// `cohort::CohortLock` hands the lock over among the CPUs of the same cluster.
+/// The default function puts all CPUs in cluster 0.
+pub fn set_cluster_of_fn(f: fn(usize) -> usize) {
+    let ptr = f as *const () as *mut ();
+    CLUSTER_OF_FN.store(ptr, Ordering::Relaxed);
+}
+
+static PANICKING_FN: AtomicPtr<()> = AtomicPtr::new(default_panicking as *mut ());
+
+#[cfg(not(feature = "std"))]
+fn default_panicking() -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+fn default_panicking() -> bool {
+    std::thread::panicking()
+}
+
+/// Return whether the current task is unwinding by calling the function registered by `set_panicking_fn`.
+#[cfg(not(loom))]
+#[inline(always)]
+pub(crate) fn panicking() -> bool {
+    let panicking = PANICKING_FN.load(Ordering::Relaxed);
+    let panicking = unsafe { core::mem::transmute::<*mut (), fn() -> bool>(panicking) };
+    panicking()
+}
+
+/// Set the function returning whether the current task is unwinding from a panic.
+///
+/// The locks of `poison` are poisoned when their guards are dropped while it returns `true`.
+/// Without `std`, the default function always returns `false`,
+/// so locks are never poisoned until a kernel catching panics per task registers one.
+pub fn set_panicking_fn(f: fn() -> bool) {
+    let ptr = f as *const () as *mut ();
+    PANICKING_FN.store(ptr, Ordering::Relaxed);
+}
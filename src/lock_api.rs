@@ -0,0 +1,255 @@
+//! # Raw locks for `lock_api`
+//!
+//! The raw parts of `SpinLock` and `RwLock` implement the traits of `lock_api`,
+//! so containers generic over `lock_api::RawMutex` or `lock_api::RawRwLock` can use them.
+//! `PooledMCSLock` is an MCS lock taking its node from the per-CPU node pool,
+//! because `lock_api::RawMutex::lock` cannot be given a node.
+//!
+//! Like the guards of this crate, the raw locks disable interrupts while they are held.
+//! Unlike the guards, interrupts are saved and restored per CPU,
+//! so a guard of `lock_api` must be dropped on the CPU that made it.
+//!
+//! ```
+//! use awkernel_sync::lock_api::{MCSMutex, RwLock, SpinMutex};
+//!
+//! let spin = SpinMutex::new(0);
+//! *spin.lock() += 1;
+//!
+//! let mcs = MCSMutex::new(0);
+//! *mcs.lock() += 1;
+//!
+//! let rwlock: RwLock<_> = RwLock::new(0);
+//! let r1 = rwlock.read();
+//! let r2 = rwlock.read();
+//! assert_eq!(*r1 + *r2 + *spin.lock() + *mcs.lock(), 2);
+//! ```
+
+use core::cell::UnsafeCell;
+
+use crate::{
+    interrupt_guard::{disable_nested, restore_nested},
+    mcs::{node_pool::PooledNode, MCSLock},
+    rwlock::{
+        PhaseFairQueue, PhaseFairTicket, RawRwLock, RawRwLockUpgrade, ReaderPreferring,
+        WriterPreferring,
+    },
+    spinlock::SpinLock,
+};
+
+/// `lock_api::Mutex` with `SpinLock`.
+pub type SpinMutex<T> = ::lock_api::Mutex<SpinLock<()>, T>;
+
+/// `lock_api::Mutex` with `PooledMCSLock`.
+pub type MCSMutex<T> = ::lock_api::Mutex<PooledMCSLock, T>;
+
+/// `lock_api::RwLock` with a policy of `crate::rwlock`.
+pub type RwLock<T, P = WriterPreferring> = ::lock_api::RwLock<P, T>;
+
+unsafe impl ::lock_api::RawMutex for SpinLock<()> {
+    const INIT: Self = SpinLock::new(());
+
+    type GuardMarker = ::lock_api::GuardNoSend;
+
+    #[inline(always)]
+    fn lock(&self) {
+        disable_nested();
+        self.lock_raw();
+    }
+
+    #[inline(always)]
+    fn try_lock(&self) -> bool {
+        disable_nested();
+        if self.try_lock_raw() {
+            return true;
+        }
+
+        unsafe { restore_nested() };
+        false
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self) {
+        self.unlock_raw();
+        restore_nested();
+    }
+}
+
+/// `SpinLock` has no queue to hand the lock over, so this is the same as `unlock`.
+unsafe impl ::lock_api::RawMutexFair for SpinLock<()> {
+    #[inline(always)]
+    unsafe fn unlock_fair(&self) {
+        ::lock_api::RawMutex::unlock(self);
+    }
+}
+
+/// An MCS lock for `lock_api`, which borrows a node from the per-CPU node pool.
+///
+/// See `MCSLock::lock_pooled` for the limit of the pool.
+pub struct PooledMCSLock {
+    lock: MCSLock<()>,
+
+    /// The node of the holder, accessed only by the holder.
+    holder: UnsafeCell<Option<PooledNode>>,
+}
+
+unsafe impl Sync for PooledMCSLock {}
+unsafe impl Send for PooledMCSLock {}
+
+unsafe impl ::lock_api::RawMutex for PooledMCSLock {
+    const INIT: Self = PooledMCSLock {
+        lock: MCSLock::new(()),
+        holder: UnsafeCell::new(None),
+    };
+
+    type GuardMarker = ::lock_api::GuardNoSend;
+
+    #[inline(always)]
+    fn lock(&self) {
+        // interrupts must be disabled before borrowing a per-CPU node
+        disable_nested();
+        let pooled = PooledNode::new();
+
+        unsafe {
+            self.lock.lock_raw(&mut *pooled.get());
+            *self.holder.get() = Some(pooled);
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock(&self) -> bool {
+        disable_nested();
+        let pooled = PooledNode::new();
+
+        unsafe {
+            if self.lock.try_lock_raw(&mut *pooled.get()) {
+                *self.holder.get() = Some(pooled);
+                return true;
+            }
+
+            drop(pooled);
+            restore_nested();
+        }
+
+        false
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self) {
+        let pooled = (*self.holder.get()).take().unwrap();
+        self.lock.unlock_raw(&*pooled.get());
+
+        drop(pooled);
+        restore_nested();
+    }
+}
+
+/// `MCSLock` always hands the lock over to the next waiter, so this is the same as `unlock`.
+unsafe impl ::lock_api::RawMutexFair for PooledMCSLock {
+    #[inline(always)]
+    unsafe fn unlock_fair(&self) {
+        ::lock_api::RawMutex::unlock(self);
+    }
+}
+
+macro_rules! impl_raw_rwlock {
+    ($policy:ty) => {
+        unsafe impl ::lock_api::RawRwLock for $policy {
+            const INIT: Self = <$policy as RawRwLock>::INIT;
+
+            type GuardMarker = ::lock_api::GuardNoSend;
+
+            #[inline(always)]
+            fn lock_shared(&self) {
+                disable_nested();
+                RawRwLock::lock_shared(self);
+            }
+
+            #[inline(always)]
+            fn try_lock_shared(&self) -> bool {
+                disable_nested();
+                if RawRwLock::try_lock_shared(self) {
+                    return true;
+                }
+
+                unsafe { restore_nested() };
+                false
+            }
+
+            #[inline(always)]
+            unsafe fn unlock_shared(&self) {
+                RawRwLock::unlock_shared(self);
+                restore_nested();
+            }
+
+            #[inline(always)]
+            fn lock_exclusive(&self) {
+                disable_nested();
+                RawRwLock::lock_exclusive(self);
+            }
+
+            #[inline(always)]
+            fn try_lock_exclusive(&self) -> bool {
+                disable_nested();
+                if RawRwLock::try_lock_exclusive(self) {
+                    return true;
+                }
+
+                unsafe { restore_nested() };
+                false
+            }
+
+            #[inline(always)]
+            unsafe fn unlock_exclusive(&self) {
+                RawRwLock::unlock_exclusive(self);
+                restore_nested();
+            }
+        }
+
+        unsafe impl ::lock_api::RawRwLockDowngrade for $policy {
+            #[inline(always)]
+            unsafe fn downgrade(&self) {
+                RawRwLock::downgrade(self);
+            }
+        }
+    };
+}
+
+impl_raw_rwlock!(WriterPreferring);
+impl_raw_rwlock!(ReaderPreferring);
+impl_raw_rwlock!(PhaseFairTicket);
+impl_raw_rwlock!(PhaseFairQueue);
+
+unsafe impl ::lock_api::RawRwLockUpgrade for WriterPreferring {
+    #[inline(always)]
+    fn lock_upgradable(&self) {
+        disable_nested();
+        RawRwLockUpgrade::lock_upgradable(self);
+    }
+
+    #[inline(always)]
+    fn try_lock_upgradable(&self) -> bool {
+        disable_nested();
+        if RawRwLockUpgrade::try_lock_upgradable(self) {
+            return true;
+        }
+
+        unsafe { restore_nested() };
+        false
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_upgradable(&self) {
+        RawRwLockUpgrade::unlock_upgradable(self);
+        restore_nested();
+    }
+
+    #[inline(always)]
+    unsafe fn upgrade(&self) {
+        RawRwLockUpgrade::upgrade(self);
+    }
+
+    #[inline(always)]
+    unsafe fn try_upgrade(&self) -> bool {
+        RawRwLockUpgrade::try_upgrade(self)
+    }
+}
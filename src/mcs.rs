@@ -1,42 +1,152 @@
-use core::{marker::PhantomData, ptr::null_mut};
+use core::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::null_mut,
+};
 
 #[cfg(not(loom))]
 use core::{
     cell::UnsafeCell,
-    ops::{Deref, DerefMut},
+    hint,
     sync::atomic::{fence, AtomicBool, AtomicPtr, Ordering},
 };
 
 #[cfg(loom)]
 use loom::{
     cell::UnsafeCell,
+    hint,
     sync::atomic::{fence, AtomicBool, AtomicPtr, Ordering},
 };
 
+use crate::{
+    access::WriteAccess,
+    mutex::{RawLock, RawLockSend},
+};
+
+#[cfg(not(loom))]
+pub(crate) mod node_pool;
+
 pub struct MCSLock<T: Send> {
-    last: AtomicPtr<MCSNode<T>>,
+    last: AtomicPtr<MCSNode>,
+
+    /// Set when `lock_until` is called for the first time.
+    /// After that, hand-offs are serialized with waiters leaving the queue by `unlink`.
+    abortable: AtomicBool,
+
+    /// Spinlock protecting the links between nodes while a waiter leaves the queue.
+    unlink: AtomicBool,
+
     data: UnsafeCell<T>,
 }
 
-pub struct MCSNode<T> {
-    next: AtomicPtr<MCSNode<T>>,
+/// A queue node of `MCSLock`.
+///
+/// A node does not depend on the type of the protected data,
+/// so it can be reused for locks of different types.
+pub struct MCSNode {
+    next: AtomicPtr<MCSNode>,
+    prev: AtomicPtr<MCSNode>,
     locked: AtomicBool,
 }
 
-impl<T> Default for MCSNode<T> {
+impl Default for MCSNode {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<T> MCSNode<T> {
+impl MCSNode {
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub const fn new() -> Self {
+        MCSNode {
+            next: AtomicPtr::new(null_mut()),
+            prev: AtomicPtr::new(null_mut()),
+            locked: AtomicBool::new(false),
+        }
+    }
+
+    #[cfg(loom)]
     #[inline(always)]
     pub fn new() -> Self {
         MCSNode {
             next: AtomicPtr::new(null_mut()),
+            prev: AtomicPtr::new(null_mut()),
             locked: AtomicBool::new(false),
         }
     }
+
+    #[inline(always)]
+    fn reset(&mut self) {
+        self.next.store(null_mut(), Ordering::Relaxed);
+        self.prev.store(null_mut(), Ordering::Relaxed);
+        self.locked.store(false, Ordering::Relaxed);
+    }
+}
+
+/// A fixed number of `MCSNode`s for code that acquires several locks in sequence.
+///
+/// ```
+/// use awkernel_sync::mcs::{MCSLock, MCSNodeArray};
+///
+/// let a = MCSLock::new(1);
+/// let b = MCSLock::new("b");
+///
+/// let mut nodes = MCSNodeArray::<2>::new();
+/// let [node_a, node_b] = nodes.each_mut();
+///
+/// let guard_a = a.lock(node_a);
+/// let guard_b = b.lock(node_b);
+/// assert_eq!((*guard_a, *guard_b), (1, "b"));
+/// ```
+pub struct MCSNodeArray<const N: usize> {
+    nodes: [MCSNode; N],
+}
+
+impl<const N: usize> Default for MCSNodeArray<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MCSNodeArray<N> {
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub const fn new() -> Self {
+        MCSNodeArray {
+            nodes: [const { MCSNode::new() }; N],
+        }
+    }
+
+    #[cfg(loom)]
+    #[inline(always)]
+    pub fn new() -> Self {
+        MCSNodeArray {
+            nodes: core::array::from_fn(|_| MCSNode::new()),
+        }
+    }
+
+    /// Borrow all nodes at once.
+    #[inline(always)]
+    pub fn each_mut(&mut self) -> [&mut MCSNode; N] {
+        self.nodes.each_mut()
+    }
+}
+
+impl<const N: usize> Deref for MCSNodeArray<N> {
+    type Target = [MCSNode];
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.nodes
+    }
+}
+
+impl<const N: usize> DerefMut for MCSNodeArray<N> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.nodes
+    }
 }
 
 impl<T: Send> MCSLock<T> {
@@ -44,6 +154,8 @@ impl<T: Send> MCSLock<T> {
     pub const fn new(v: T) -> MCSLock<T> {
         MCSLock {
             last: AtomicPtr::new(null_mut()),
+            abortable: AtomicBool::new(false),
+            unlink: AtomicBool::new(false),
             data: UnsafeCell::new(v),
         }
     }
@@ -52,33 +164,66 @@ impl<T: Send> MCSLock<T> {
     pub fn new(v: T) -> MCSLock<T> {
         MCSLock {
             last: AtomicPtr::new(null_mut()),
+            abortable: AtomicBool::new(false),
+            unlink: AtomicBool::new(false),
             data: UnsafeCell::new(v),
         }
     }
 
     #[inline(always)]
-    pub fn try_lock<'a>(&'a self, node: &'a mut MCSNode<T>) -> Option<MCSLockGuard<'a, T>> {
-        node.next.store(null_mut(), Ordering::Relaxed);
-        node.locked.store(false, Ordering::Relaxed);
+    pub fn try_lock<'a>(&'a self, node: &'a mut MCSNode) -> Option<MCSLockGuard<'a, T>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.try_lock_guard(MCSLockGuard::new(self, node, _interrupt_guard))
+    }
 
+    /// acquire lock
+    #[inline(always)]
+    pub fn lock<'a>(&'a self, node: &'a mut MCSNode) -> MCSLockGuard<'a, T> {
         let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.lock_guard(MCSLockGuard::new(self, node, _interrupt_guard))
+    }
 
-        // set myself as the last node
-        let mut guard = MCSLockGuard {
-            node,
-            mcs_lock: self,
-            need_unlock: true,
-            _interrupt_guard,
-            _phantom: PhantomData,
-        };
+    /// Try to acquire the lock with a node borrowed from the per-CPU node pool.
+    ///
+    /// See `lock_pooled`.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub fn try_lock_pooled(&self) -> Option<MCSLockGuard<'_, T>> {
+        // interrupts must be disabled before borrowing a per-CPU node
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        let pooled = node_pool::PooledNode::new();
 
-        let ptr = guard.node as *mut MCSNode<T>;
+        let mut guard = MCSLockGuard::new(self, unsafe { &mut *pooled.get() }, _interrupt_guard);
+        guard._pooled = Some(pooled);
 
-        if self
-            .last
-            .compare_exchange(null_mut(), ptr, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok()
-        {
+        self.try_lock_guard(guard)
+    }
+
+    /// Acquire the lock with a node borrowed from the per-CPU node pool
+    /// instead of a node given by the caller.
+    ///
+    /// The node is returned to the pool when the guard is dropped.
+    /// A CPU can hold up to 8 locks acquired by `lock_pooled` or `try_lock_pooled`
+    /// at the same time, and it panics if it tries to hold more.
+    ///
+    /// The current CPU is given by the function registered by `crate::set_cpu_id_fn`.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub fn lock_pooled(&self) -> MCSLockGuard<'_, T> {
+        // interrupts must be disabled before borrowing a per-CPU node
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        let pooled = node_pool::PooledNode::new();
+
+        let mut guard = MCSLockGuard::new(self, unsafe { &mut *pooled.get() }, _interrupt_guard);
+        guard._pooled = Some(pooled);
+
+        self.lock_guard(guard)
+    }
+
+    #[inline(always)]
+    fn try_lock_guard<'a>(&'a self, mut guard: MCSLockGuard<'a, T>) -> Option<MCSLockGuard<'a, T>> {
+        if self.try_acquire(guard.node) {
+            guard.access = WriteAccess::start(&self.data);
             Some(guard)
         } else {
             guard.need_unlock = false;
@@ -86,62 +231,446 @@ impl<T: Send> MCSLock<T> {
         }
     }
 
-    /// acquire lock
     #[inline(always)]
-    pub fn lock<'a>(&'a self, node: &'a mut MCSNode<T>) -> MCSLockGuard<'a, T> {
-        node.next.store(null_mut(), Ordering::Relaxed);
-        node.locked.store(false, Ordering::Relaxed);
+    fn lock_guard<'a>(&'a self, mut guard: MCSLockGuard<'a, T>) -> MCSLockGuard<'a, T> {
+        self.acquire(guard.node);
+        guard.access = WriteAccess::start(&self.data);
+        guard
+    }
 
-        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+    /// Try to acquire the lock with `node` without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// If this returns `true`, `node` must not be moved or dropped
+    /// until `unlock_raw` is called with it.
+    /// Interrupts should be disabled while the lock is held.
+    #[inline(always)]
+    pub unsafe fn try_lock_raw(&self, node: &mut MCSNode) -> bool {
+        node.reset();
+        self.try_acquire(node)
+    }
+
+    /// Acquire the lock with `node` without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// `node` must not be moved or dropped until `unlock_raw` is called with it.
+    /// Interrupts should be disabled while the lock is held.
+    #[inline(always)]
+    pub unsafe fn lock_raw(&self, node: &mut MCSNode) {
+        node.reset();
+        self.acquire(node);
+    }
+
+    /// Release the lock acquired by `lock_raw` or `try_lock_raw`.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held with `node`.
+    #[inline(always)]
+    pub unsafe fn unlock_raw(&self, node: &MCSNode) {
+        self.unlock(node);
+    }
+
+    /// Return a pointer to the protected data.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
 
+    #[inline(always)]
+    fn try_acquire(&self, node: &MCSNode) -> bool {
         // set myself as the last node
-        let guard = MCSLockGuard {
-            node,
-            mcs_lock: self,
-            need_unlock: true,
-            _interrupt_guard,
-            _phantom: Default::default(),
-        };
+        let ptr = node as *const MCSNode as *mut MCSNode;
+
+        self.last
+            .compare_exchange(null_mut(), ptr, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    #[inline(always)]
+    fn acquire(&self, node: &MCSNode) {
+        // set myself as the last node
+        if self.enqueue(node) {
+            return;
+        }
+
+        // spin until other thread sets locked true
+        super::mwait::wait_while_false(&node.locked);
+
+        fence(Ordering::Acquire);
+    }
 
-        let ptr = guard.node as *mut MCSNode<T>;
+    /// Acquire the lock, but give up when `uptime()` reaches `deadline`.
+    ///
+    /// `deadline` is an uptime in microseconds given by the function registered by
+    /// `crate::set_uptime_fn`.
+    /// If the deadline passes while waiting, `node` is removed from the queue
+    /// and `None` is returned.
+    #[inline(always)]
+    pub fn lock_timeout<'a>(
+        &'a self,
+        node: &'a mut MCSNode,
+        deadline: u64,
+    ) -> Option<MCSLockGuard<'a, T>> {
+        self.lock_until(node, || crate::uptime() >= deadline)
+    }
+
+    /// Acquire the lock, but give up when `cancel` returns `true`.
+    ///
+    /// `cancel` is polled while waiting with interrupts disabled.
+    /// If it returns `true` before the lock is handed over,
+    /// `node` is removed from the queue and `None` is returned.
+    /// After this function returns, no other thread accesses `node`.
+    #[inline(always)]
+    pub fn lock_until<'a, F>(
+        &'a self,
+        node: &'a mut MCSNode,
+        cancel: F,
+    ) -> Option<MCSLockGuard<'a, T>>
+    where
+        F: FnMut() -> bool,
+    {
+        // this must be visible before the node is linked to its predecessor
+        self.abortable.store(true, Ordering::Relaxed);
+
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        let mut guard = MCSLockGuard::new(self, node, _interrupt_guard);
+
+        if self.enqueue(guard.node) {
+            guard.access = WriteAccess::start(&self.data);
+            return Some(guard);
+        }
+
+        if super::mwait::wait_while_false_until(&guard.node.locked, cancel)
+            || self.leave(guard.node)
+        {
+            fence(Ordering::Acquire);
+            guard.access = WriteAccess::start(&self.data);
+            Some(guard)
+        } else {
+            guard.need_unlock = false;
+            None
+        }
+    }
+
+    /// Set `node` as the last node and link it to its predecessor.
+    /// Return `true` if the lock was acquired without waiting.
+    #[inline(always)]
+    fn enqueue(&self, node: &MCSNode) -> bool {
+        let ptr = node as *const MCSNode as *mut MCSNode;
         let prev = self.last.swap(ptr, Ordering::AcqRel);
 
         // if prev is null then nobody is trying to acquire lock
         if prev.is_null() {
-            return guard;
+            return true;
         }
 
         // enqueue myself
+        node.prev.store(prev, Ordering::Relaxed);
         let prev = unsafe { &*prev };
         prev.next.store(ptr, Ordering::Release);
 
-        // spin until other thread sets locked true
-        super::mwait::wait_while_false(&guard.node.locked);
+        false
+    }
 
-        fence(Ordering::Acquire);
+    /// Remove the waiting `node` from the queue.
+    ///
+    /// Return `true` if the lock had been handed over to `node` before it could leave.
+    fn leave(&self, node: &MCSNode) -> bool {
+        self.lock_unlink();
 
-        guard
+        if node.locked.load(Ordering::Acquire) {
+            self.unlock_unlink();
+            return true;
+        }
+
+        let ptr = node as *const MCSNode as *mut MCSNode;
+
+        // The predecessor cannot leave or hand the lock over while `unlink` is held.
+        let prev = unsafe { &*node.prev.load(Ordering::Relaxed) };
+
+        let mut next = node.next.load(Ordering::Acquire);
+        if next.is_null() {
+            // if node is the last node, the predecessor becomes the last node
+            prev.next.store(null_mut(), Ordering::Relaxed);
+            if self
+                .last
+                .compare_exchange(
+                    ptr,
+                    prev as *const MCSNode as *mut MCSNode,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                self.unlock_unlink();
+                return false;
+            }
+
+            // other thread is entering lock and wait the link
+            super::mwait::wait_while_null(&node.next);
+            next = node.next.load(Ordering::Acquire);
+        }
+
+        // link the predecessor and the successor
+        let next = unsafe { &*next };
+        next.prev
+            .store(prev as *const MCSNode as *mut MCSNode, Ordering::Relaxed);
+        prev.next
+            .store(next as *const MCSNode as *mut MCSNode, Ordering::Release);
+
+        self.unlock_unlink();
+
+        false
+    }
+
+    /// Return `true` if a waiter has queued up after `node`, which holds the lock.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub(crate) fn has_successor(&self, node: &MCSNode) -> bool {
+        let ptr = node as *const MCSNode as *mut MCSNode;
+        !node.next.load(Ordering::Relaxed).is_null() || self.last.load(Ordering::Relaxed) != ptr
+    }
+
+    /// Release the lock held by `node`.
+    #[inline(always)]
+    fn unlock(&self, node: &MCSNode) {
+        let ptr = node as *const MCSNode as *mut MCSNode;
+
+        // if next node is null and self is the last node
+        // set the last node to null
+        if node.next.load(Ordering::Relaxed).is_null() {
+            if self
+                .last
+                .compare_exchange(ptr, null_mut(), Ordering::Release, Ordering::Acquire)
+                .is_ok()
+            {
+                return;
+            }
+
+            // the successor may leave before linking itself
+            if self.abortable.load(Ordering::Relaxed) {
+                self.unlock_abortable(node);
+                return;
+            }
+
+            // other thread is entering lock and wait the execution
+            super::mwait::wait_while_null(&node.next);
+        }
+
+        let next = node.next.load(Ordering::Acquire);
+
+        // the successor may be leaving the queue
+        if next.is_null() || self.abortable.load(Ordering::Relaxed) {
+            self.unlock_abortable(node);
+            return;
+        }
+
+        // make next thread executable
+        let next = unsafe { &*next };
+        next.locked.store(true, Ordering::Release);
+    }
+
+    /// Release the lock while the successor may be leaving the queue.
+    fn unlock_abortable(&self, node: &MCSNode) {
+        let ptr = node as *const MCSNode as *mut MCSNode;
+
+        self.lock_unlink();
+
+        // the successor may have left, so read it again
+        let next = loop {
+            let next = node.next.load(Ordering::Acquire);
+            if !next.is_null() {
+                break next;
+            }
+
+            if self
+                .last
+                .compare_exchange(ptr, null_mut(), Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.unlock_unlink();
+                return;
+            }
+
+            hint::spin_loop();
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        };
+
+        // make next thread executable
+        let next = unsafe { &*next };
+        next.locked.store(true, Ordering::Release);
+
+        self.unlock_unlink();
+    }
+
+    #[inline(always)]
+    fn lock_unlink(&self) {
+        while self
+            .unlink
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    fn unlock_unlink(&self) {
+        self.unlink.store(false, Ordering::Release);
     }
 }
 
+unsafe impl RawLock for MCSLock<()> {
+    #[cfg(not(loom))]
+    const INIT: Self = MCSLock::new(());
+
+    #[cfg(loom)]
+    fn new() -> Self {
+        MCSLock::new(())
+    }
+
+    const USES_NODE: bool = true;
+
+    #[inline(always)]
+    unsafe fn lock(&self, node: &mut MCSNode) {
+        self.lock_raw(node);
+    }
+
+    #[inline(always)]
+    unsafe fn try_lock(&self, node: &mut MCSNode) -> bool {
+        self.try_lock_raw(node)
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self, node: &MCSNode) {
+        self.unlock_raw(node);
+    }
+}
+
+unsafe impl RawLockSend for MCSLock<()> {}
+
 unsafe impl<T: Send> Sync for MCSLock<T> {}
 unsafe impl<T: Send> Send for MCSLock<T> {}
 
 pub struct MCSLockGuard<'a, T: Send> {
-    node: &'a mut MCSNode<T>,
+    node: &'a mut MCSNode,
     mcs_lock: &'a MCSLock<T>,
     need_unlock: bool,
+    access: WriteAccess<T>,
+
+    /// The node borrowed from the per-CPU pool.
+    /// This must be dropped before `_interrupt_guard`.
+    #[cfg(not(loom))]
+    _pooled: Option<node_pool::PooledNode>,
+
     _interrupt_guard: crate::interrupt_guard::InterruptGuard,
     _phantom: PhantomData<*mut ()>,
 }
 
+impl<'a, T: Send> MCSLockGuard<'a, T> {
+    #[inline(always)]
+    fn new(
+        mcs_lock: &'a MCSLock<T>,
+        node: &'a mut MCSNode,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        node.reset();
+
+        MCSLockGuard {
+            node,
+            mcs_lock,
+            need_unlock: true,
+            access: WriteAccess::idle(),
+            #[cfg(not(loom))]
+            _pooled: None,
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: Send> MCSLockGuard<'a, T> {
+    /// Make a guard for a part of the locked data.
+    ///
+    /// The lock is held until the returned guard is dropped.
+    /// This is an associated function to avoid conflicts with methods of `T`.
+    ///
+    /// ```
+    /// use awkernel_sync::mcs::{MCSLock, MCSLockGuard, MCSNode};
+    ///
+    /// let lock = MCSLock::new((0, 0));
+    /// let mut node = MCSNode::new();
+    ///
+    /// let mut second = MCSLockGuard::map(lock.lock(&mut node), |data| &mut data.1);
+    /// *second = 1;
+    /// ```
+    #[inline(always)]
+    pub fn map<U, F>(mut guard: Self, f: F) -> MappedMCSLockGuard<'a, T, U>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(&mut guard) as *mut U;
+        MappedMCSLockGuard {
+            _guard: guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<U, F>(mut guard: Self, f: F) -> Result<MappedMCSLockGuard<'a, T, U>, Self>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(&mut guard).map(|data| data as *mut U) {
+            Some(data) => Ok(MappedMCSLockGuard {
+                _guard: guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<U, E, F>(
+        mut guard: Self,
+        f: F,
+    ) -> Result<MappedMCSLockGuard<'a, T, U>, (Self, E)>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> Result<&mut U, E>,
+    {
+        match f(&mut guard).map(|data| data as *mut U) {
+            Ok(data) => Ok(MappedMCSLockGuard {
+                _guard: guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
 impl<T: Send> MCSLockGuard<'_, T> {
     #[cfg(loom)]
     pub fn with_mut<F, R>(&mut self, f: F) -> R
     where
         F: FnOnce(*mut T) -> R,
     {
-        self.mcs_lock.data.with_mut(f)
+        f(self.access.get(&self.mcs_lock.data))
     }
 }
 
@@ -152,55 +681,108 @@ impl<T: Send> Drop for MCSLockGuard<'_, T> {
             return;
         }
 
-        // if next node is null and self is the last node
-        // set the last node to null
-        if self.node.next.load(Ordering::Relaxed).is_null() {
-            let ptr = self.node as *mut MCSNode<T>;
-            if self
-                .mcs_lock
-                .last
-                .compare_exchange(ptr, null_mut(), Ordering::Release, Ordering::Relaxed)
-                .is_ok()
-            {
-                return;
-            }
-
-            // other thread is entering lock and wait the execution
-            super::mwait::wait_while_null(&self.node.next);
-        }
-
-        // make next thread executable
-        let next = unsafe { &mut *self.node.next.load(Ordering::Acquire) };
-        next.locked.store(true, Ordering::Release);
+        self.access.end();
+        self.mcs_lock.unlock(self.node);
     }
 }
 
-#[cfg(not(loom))]
 impl<T: Send> Deref for MCSLockGuard<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        unsafe { &*self.mcs_lock.data.get() }
+        unsafe { &*self.access.get(&self.mcs_lock.data) }
     }
 }
 
-#[cfg(not(loom))]
 impl<T: Send> DerefMut for MCSLockGuard<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.mcs_lock.data.get() }
+        unsafe { &mut *self.access.get(&self.mcs_lock.data) }
     }
 }
 
-#[cfg(not(loom))]
 impl<T: Send> AsMut<T> for MCSLockGuard<'_, T> {
     fn as_mut(&mut self) -> &mut T {
-        unsafe { &mut *self.mcs_lock.data.get() }
+        unsafe { &mut *self.access.get(&self.mcs_lock.data) }
     }
 }
 
-#[cfg(not(loom))]
 impl<T: Send> AsRef<T> for MCSLockGuard<'_, T> {
     fn as_ref(&self) -> &T {
-        unsafe { &*self.mcs_lock.data.get() }
+        unsafe { &*self.access.get(&self.mcs_lock.data) }
+    }
+}
+
+/// A guard for a part of the data protected by `MCSLock`,
+/// made by `MCSLockGuard::map`, `filter_map` or `try_map`.
+///
+/// The lock and the interrupt guard are held by the original guard inside.
+pub struct MappedMCSLockGuard<'a, T: Send, U: ?Sized> {
+    _guard: MCSLockGuard<'a, T>,
+    data: *mut U,
+}
+
+impl<'a, T: Send, U: ?Sized> MappedMCSLockGuard<'a, T, U> {
+    /// Make a guard for a part of the mapped data.
+    #[inline(always)]
+    pub fn map<V, F>(guard: Self, f: F) -> MappedMCSLockGuard<'a, T, V>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> &mut V,
+    {
+        let data = f(unsafe { &mut *guard.data }) as *mut V;
+        MappedMCSLockGuard {
+            _guard: guard._guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<V, F>(guard: Self, f: F) -> Result<MappedMCSLockGuard<'a, T, V>, Self>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> Option<&mut V>,
+    {
+        match f(unsafe { &mut *guard.data }).map(|data| data as *mut V) {
+            Some(data) => Ok(MappedMCSLockGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<V, E, F>(guard: Self, f: F) -> Result<MappedMCSLockGuard<'a, T, V>, (Self, E)>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> Result<&mut V, E>,
+    {
+        match f(unsafe { &mut *guard.data }).map(|data| data as *mut V) {
+            Ok(data) => Ok(MappedMCSLockGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
+impl<T: Send, U: ?Sized> Deref for MappedMCSLockGuard<'_, T, U> {
+    type Target = U;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<T: Send, U: ?Sized> DerefMut for MappedMCSLockGuard<'_, T, U> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data }
     }
 }
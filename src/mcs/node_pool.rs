@@ -0,0 +1,90 @@
+//! Per-CPU pool of `MCSNode`s used by `MCSLock::lock_pooled` and `MCSLock::try_lock_pooled`.
+//!
+//! Like the per-CPU nodes of Linux's qspinlock, each CPU has a small array of nodes,
+//! and one node is used for each nesting level of lock acquisition.
+//! Because a node of `MCSLock` is used until the lock is released,
+//! and locks can be released in any order,
+//! nodes in use are tracked by a bitmap instead of a counter.
+//!
+//! A node is taken on the current CPU, but it may be returned on another CPU,
+//! e.g. when the writer guard of `rwlock::PhaseFairQueue` made by `RwLock::write_arc`
+//! is dropped by another thread.
+//! So the bitmap is updated by atomic read-modify-write operations.
+
+use super::MCSNode;
+use crate::NUM_MAX_CPU;
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The number of nodes per CPU.
+/// This is the maximum number of pooled locks a CPU can hold at the same time.
+pub(super) const NUM_NODES: usize = 8;
+
+#[repr(align(64))]
+struct PerCpuNodes {
+    /// Bitmap of nodes in use.
+    /// Only the owner CPU sets bits, but any CPU clears them.
+    used: AtomicUsize,
+    nodes: [UnsafeCell<MCSNode>; NUM_NODES],
+}
+
+/// Only the owner CPU allocates nodes,
+/// and a node is accessed only by the lock and the holder of the `PooledNode`.
+unsafe impl Sync for PerCpuNodes {}
+
+impl PerCpuNodes {
+    const fn new() -> Self {
+        PerCpuNodes {
+            used: AtomicUsize::new(0),
+            nodes: [const { UnsafeCell::new(MCSNode::new()) }; NUM_NODES],
+        }
+    }
+}
+
+static NODE_POOL: [PerCpuNodes; NUM_MAX_CPU] = [const { PerCpuNodes::new() }; NUM_MAX_CPU];
+
+/// A node borrowed from the pool of the current CPU.
+/// The node is returned to the pool when this is dropped.
+///
+/// Interrupts must be disabled while this is made
+/// so that the CPU does not change before the node is marked as used.
+pub(crate) struct PooledNode {
+    cpu: usize,
+    index: usize,
+}
+
+impl PooledNode {
+    #[inline(always)]
+    pub(crate) fn new() -> Self {
+        let cpu = crate::cpu_id();
+        let pool = &NODE_POOL[cpu];
+
+        // synchronize with the CPU that returned the node
+        let used = pool.used.load(Ordering::Acquire);
+        let index = (!used).trailing_zeros() as usize;
+        if index >= NUM_NODES {
+            panic!("CPU {cpu} holds more than {NUM_NODES} pooled MCS locks");
+        }
+
+        // other CPUs may clear the bits of the nodes returned by them
+        pool.used.fetch_or(1 << index, Ordering::Relaxed);
+
+        PooledNode { cpu, index }
+    }
+
+    #[inline(always)]
+    pub(crate) fn get(&self) -> *mut MCSNode {
+        NODE_POOL[self.cpu].nodes[self.index].get()
+    }
+}
+
+impl Drop for PooledNode {
+    #[inline(always)]
+    fn drop(&mut self) {
+        NODE_POOL[self.cpu]
+            .used
+            .fetch_and(!(1 << self.index), Ordering::Release);
+    }
+}
@@ -0,0 +1,377 @@
+//! A fair queue-based reader-writer lock by Mellor-Crummey and Scott.
+//!
+//! Readers and writers wait in one FIFO queue and each of them spins on its own node.
+//! Consecutive readers in the queue hold the lock together,
+//! and a writer waits until all the readers before it leave.
+//!
+//! ```
+//! use awkernel_sync::mcs_rw::{MCSRwLock, MCSRwNode};
+//!
+//! let lock = MCSRwLock::new(0);
+//!
+//! let mut node = MCSRwNode::new();
+//! *lock.write(&mut node) += 1;
+//!
+//! let mut node1 = MCSRwNode::new();
+//! let mut node2 = MCSRwNode::new();
+//! let r1 = lock.read(&mut node1);
+//! let r2 = lock.read(&mut node2);
+//! assert_eq!(*r1 + *r2, 2);
+//! ```
+
+use core::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::null_mut,
+};
+
+#[cfg(not(loom))]
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+#[cfg(loom)]
+use loom::{
+    cell::UnsafeCell,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crate::access::{ReadAccess, WriteAccess};
+
+/// The owner of the node is waiting.
+const BLOCKED: usize = 1;
+
+/// A reader waiting behind the node asked to be woken up with it.
+const SUCCESSOR_READER: usize = 2;
+
+/// A writer is waiting behind the node.
+const SUCCESSOR_WRITER: usize = 4;
+
+pub struct MCSRwLock<T: Send> {
+    tail: AtomicPtr<MCSRwNode>,
+
+    /// The number of readers holding the lock.
+    reader_count: AtomicUsize,
+
+    /// The writer to be woken up by the last leaving reader.
+    next_writer: AtomicPtr<MCSRwNode>,
+
+    data: UnsafeCell<T>,
+}
+
+/// A queue node of `MCSRwLock`.
+///
+/// Like `MCSNode`, a node can be reused for locks of different types.
+pub struct MCSRwNode {
+    next: AtomicPtr<MCSRwNode>,
+
+    /// Set when the lock is handed over.
+    locked: AtomicBool,
+
+    /// `BLOCKED` and the class of the successor.
+    /// These are updated at once so that a reader behind can tell whether it is woken up.
+    state: AtomicUsize,
+
+    writer: AtomicBool,
+}
+
+impl Default for MCSRwNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MCSRwNode {
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub const fn new() -> Self {
+        MCSRwNode {
+            next: AtomicPtr::new(null_mut()),
+            locked: AtomicBool::new(false),
+            state: AtomicUsize::new(0),
+            writer: AtomicBool::new(false),
+        }
+    }
+
+    #[cfg(loom)]
+    #[inline(always)]
+    pub fn new() -> Self {
+        MCSRwNode {
+            next: AtomicPtr::new(null_mut()),
+            locked: AtomicBool::new(false),
+            state: AtomicUsize::new(0),
+            writer: AtomicBool::new(false),
+        }
+    }
+
+    #[inline(always)]
+    fn reset(&mut self, writer: bool) {
+        self.next.store(null_mut(), Ordering::Relaxed);
+        self.locked.store(false, Ordering::Relaxed);
+        self.state.store(BLOCKED, Ordering::Relaxed);
+        self.writer.store(writer, Ordering::Relaxed);
+    }
+
+    /// Hand the lock over to the owner of this node.
+    #[inline(always)]
+    fn unblock(&self) {
+        self.state.fetch_and(!BLOCKED, Ordering::AcqRel);
+        self.locked.store(true, Ordering::Release);
+    }
+
+    #[inline(always)]
+    fn wait_next(&self) -> &MCSRwNode {
+        super::mwait::wait_while_null(&self.next);
+        unsafe { &*self.next.load(Ordering::Acquire) }
+    }
+}
+
+impl<T: Send> MCSRwLock<T> {
+    #[cfg(not(loom))]
+    pub const fn new(v: T) -> Self {
+        Self {
+            tail: AtomicPtr::new(null_mut()),
+            reader_count: AtomicUsize::new(0),
+            next_writer: AtomicPtr::new(null_mut()),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new(v: T) -> Self {
+        Self {
+            tail: AtomicPtr::new(null_mut()),
+            reader_count: AtomicUsize::new(0),
+            next_writer: AtomicPtr::new(null_mut()),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    /// Acquire the lock for reading.
+    #[inline(always)]
+    pub fn read<'a>(&'a self, node: &'a mut MCSRwNode) -> MCSRwLockReadGuard<'a, T> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+
+        node.reset(false);
+        self.start_read(node);
+
+        MCSRwLockReadGuard {
+            node,
+            rwlock: self,
+            access: ReadAccess::start(&self.data),
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Acquire the lock for writing.
+    #[inline(always)]
+    pub fn write<'a>(&'a self, node: &'a mut MCSRwNode) -> MCSRwLockWriteGuard<'a, T> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+
+        node.reset(true);
+        self.start_write(node);
+
+        MCSRwLockWriteGuard {
+            node,
+            rwlock: self,
+            access: WriteAccess::start(&self.data),
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    fn start_read(&self, node: &MCSRwNode) {
+        let ptr = node as *const MCSRwNode as *mut MCSRwNode;
+        let pred = self.tail.swap(ptr, Ordering::AcqRel);
+
+        let state = if pred.is_null() {
+            self.reader_count.fetch_add(1, Ordering::AcqRel);
+            node.state.fetch_and(!BLOCKED, Ordering::AcqRel)
+        } else {
+            let pred = unsafe { &*pred };
+
+            // a waiting predecessor, either a writer or a blocked reader, wakes me up
+            if pred.writer.load(Ordering::Relaxed)
+                || pred
+                    .state
+                    .compare_exchange(
+                        BLOCKED,
+                        BLOCKED | SUCCESSOR_READER,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                    .is_ok()
+            {
+                pred.next.store(ptr, Ordering::Release);
+                super::mwait::wait_while_false(&node.locked);
+                fence(Ordering::Acquire);
+                node.state.load(Ordering::Relaxed)
+            } else {
+                // the predecessor is a reader holding the lock
+                self.reader_count.fetch_add(1, Ordering::AcqRel);
+                pred.next.store(ptr, Ordering::Release);
+                node.state.fetch_and(!BLOCKED, Ordering::AcqRel)
+            }
+        };
+
+        // wake up the reader that asked me while I was blocked
+        if state & SUCCESSOR_READER != 0 {
+            let next = node.wait_next();
+            self.reader_count.fetch_add(1, Ordering::AcqRel);
+            next.unblock();
+        }
+    }
+
+    #[inline(always)]
+    fn end_read(&self, node: &MCSRwNode) {
+        let ptr = node as *const MCSRwNode as *mut MCSRwNode;
+
+        if !node.next.load(Ordering::Acquire).is_null()
+            || self
+                .tail
+                .compare_exchange(ptr, null_mut(), Ordering::Release, Ordering::Relaxed)
+                .is_err()
+        {
+            let next = node.wait_next();
+            if node.state.load(Ordering::Relaxed) & SUCCESSOR_WRITER != 0 {
+                self.next_writer.store(
+                    next as *const MCSRwNode as *mut MCSRwNode,
+                    Ordering::Release,
+                );
+            }
+        }
+
+        // the last reader wakes up the writer
+        if self.reader_count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let writer = self.next_writer.swap(null_mut(), Ordering::AcqRel);
+            if !writer.is_null() {
+                unsafe { &*writer }.unblock();
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn start_write(&self, node: &MCSRwNode) {
+        let ptr = node as *const MCSRwNode as *mut MCSRwNode;
+        let pred = self.tail.swap(ptr, Ordering::AcqRel);
+
+        if pred.is_null() {
+            // readers may still hold the lock after leaving the queue.
+            // read-modify-writes, which read the latest values,
+            // make sure that either me or the last reader sees the other.
+            self.next_writer.swap(ptr, Ordering::AcqRel);
+            if self.reader_count.fetch_add(0, Ordering::AcqRel) == 0
+                && self.next_writer.swap(null_mut(), Ordering::AcqRel) == ptr
+            {
+                return;
+            }
+        } else {
+            let pred = unsafe { &*pred };
+            pred.state.fetch_or(SUCCESSOR_WRITER, Ordering::AcqRel);
+            pred.next.store(ptr, Ordering::Release);
+        }
+
+        super::mwait::wait_while_false(&node.locked);
+        fence(Ordering::Acquire);
+    }
+
+    #[inline(always)]
+    fn end_write(&self, node: &MCSRwNode) {
+        let ptr = node as *const MCSRwNode as *mut MCSRwNode;
+
+        if node.next.load(Ordering::Acquire).is_null()
+            && self
+                .tail
+                .compare_exchange(ptr, null_mut(), Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+        {
+            return;
+        }
+
+        let next = node.wait_next();
+        if !next.writer.load(Ordering::Relaxed) {
+            self.reader_count.fetch_add(1, Ordering::AcqRel);
+        }
+        next.unblock();
+    }
+}
+
+unsafe impl<T: Send> Sync for MCSRwLock<T> {}
+unsafe impl<T: Send> Send for MCSRwLock<T> {}
+
+pub struct MCSRwLockReadGuard<'a, T: Send> {
+    node: &'a mut MCSRwNode,
+    rwlock: &'a MCSRwLock<T>,
+    access: ReadAccess<T>,
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<T: Send> MCSRwLockReadGuard<'_, T> {
+    #[cfg(loom)]
+    pub fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(*const T) -> R,
+    {
+        f(self.access.get(&self.rwlock.data))
+    }
+}
+
+impl<T: Send> Drop for MCSRwLockReadGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.access.end();
+        self.rwlock.end_read(self.node);
+    }
+}
+
+impl<T: Send> Deref for MCSRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.access.get(&self.rwlock.data) }
+    }
+}
+
+pub struct MCSRwLockWriteGuard<'a, T: Send> {
+    node: &'a mut MCSRwNode,
+    rwlock: &'a MCSRwLock<T>,
+    access: WriteAccess<T>,
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<T: Send> MCSRwLockWriteGuard<'_, T> {
+    #[cfg(loom)]
+    pub fn with_mut<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(*mut T) -> R,
+    {
+        f(self.access.get(&self.rwlock.data))
+    }
+}
+
+impl<T: Send> Drop for MCSRwLockWriteGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.access.end();
+        self.rwlock.end_write(self.node);
+    }
+}
+
+impl<T: Send> Deref for MCSRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.access.get(&self.rwlock.data) }
+    }
+}
+
+impl<T: Send> DerefMut for MCSRwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.access.get(&self.rwlock.data) }
+    }
+}
@@ -0,0 +1,325 @@
+//! # Time-published MCS lock
+//!
+//! `MCSTPLock` is an MCS lock tolerating preemption of waiters,
+//! which happens when vCPUs of a guest are descheduled by the hypervisor.
+//! With a plain MCS lock, a preempted waiter stalls every waiter behind it when the lock is handed over.
+//!
+//! A waiter publishes the time while it spins.
+//! The releaser skips a waiter whose time is older than the threshold,
+//! because such a waiter is likely preempted, and hands the lock over to the next one.
+//! A skipped waiter notices it when it runs again, and queues up again at the tail.
+//!
+//! The time is given by the function registered by `crate::set_uptime_fn`.
+//! Without a clock, no waiter is skipped and the lock works like `MCSLock`.
+//!
+//! ```
+//! use awkernel_sync::mcs_tp::{MCSTPLock, MCSTPNode};
+//!
+//! let lock = MCSTPLock::new(0).with_threshold(500);
+//!
+//! let mut node = MCSTPNode::new();
+//! *lock.lock(&mut node) += 1;
+//!
+//! let mut node = MCSTPNode::new();
+//! assert_eq!(*lock.try_lock(&mut node).unwrap(), 1);
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    hint,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::null_mut,
+    sync::atomic::{AtomicPtr, AtomicU64, AtomicU8, Ordering},
+};
+
+use crate::access::WriteAccess;
+
+/// The default threshold in microseconds.
+pub const DEFAULT_THRESHOLD: u64 = 1000;
+
+/// The waiter is spinning.
+const WAITING: u8 = 0;
+
+/// The lock is handed over to the waiter.
+const GRANTED: u8 = 1;
+
+/// The waiter is skipped, and the releaser is still reading the node.
+const REMOVED: u8 = 2;
+
+/// The waiter is skipped, and the node can be used again.
+const RECYCLED: u8 = 3;
+
+pub struct MCSTPLock<T: Send> {
+    last: AtomicPtr<MCSTPNode>,
+
+    /// A waiter whose time is older than this in microseconds is skipped.
+    threshold: u64,
+
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for MCSTPLock<T> {}
+unsafe impl<T: Send> Send for MCSTPLock<T> {}
+
+/// A queue node of `MCSTPLock`.
+pub struct MCSTPNode {
+    next: AtomicPtr<MCSTPNode>,
+    status: AtomicU8,
+
+    /// The time published by the waiter.
+    time: AtomicU64,
+}
+
+impl Default for MCSTPNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MCSTPNode {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        MCSTPNode {
+            next: AtomicPtr::new(null_mut()),
+            status: AtomicU8::new(WAITING),
+            time: AtomicU64::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn reset(&mut self) {
+        self.next.store(null_mut(), Ordering::Relaxed);
+        self.status.store(WAITING, Ordering::Relaxed);
+        self.time.store(crate::uptime(), Ordering::Relaxed);
+    }
+}
+
+impl<T: Send> MCSTPLock<T> {
+    pub const fn new(v: T) -> Self {
+        MCSTPLock {
+            last: AtomicPtr::new(null_mut()),
+            threshold: DEFAULT_THRESHOLD,
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    /// Set the threshold in microseconds to regard a waiter as preempted.
+    ///
+    /// It should be longer than the interval between time updates of a running waiter,
+    /// including the time of interrupt handlers and hypervisor exits.
+    #[inline(always)]
+    pub const fn with_threshold(mut self, threshold: u64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    #[inline(always)]
+    pub fn try_lock<'a>(&'a self, node: &'a mut MCSTPNode) -> Option<MCSTPLockGuard<'a, T>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        if unsafe { self.try_lock_raw(node) } {
+            Some(MCSTPLockGuard::new(self, node, _interrupt_guard))
+        } else {
+            None
+        }
+    }
+
+    /// acquire lock
+    #[inline(always)]
+    pub fn lock<'a>(&'a self, node: &'a mut MCSTPNode) -> MCSTPLockGuard<'a, T> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        unsafe { self.lock_raw(node) };
+        MCSTPLockGuard::new(self, node, _interrupt_guard)
+    }
+
+    /// Try to acquire the lock with `node` without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// If this returns `true`, `node` must not be moved or dropped
+    /// until `unlock_raw` is called with it.
+    /// Interrupts should be disabled while the lock is held.
+    #[inline(always)]
+    pub unsafe fn try_lock_raw(&self, node: &mut MCSTPNode) -> bool {
+        node.reset();
+
+        let ptr = node as *mut MCSTPNode;
+        self.last
+            .compare_exchange(null_mut(), ptr, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Acquire the lock with `node` without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// `node` must not be moved or dropped until `unlock_raw` is called with it.
+    /// Interrupts should be disabled while the lock is held or waited for.
+    #[inline(always)]
+    pub unsafe fn lock_raw(&self, node: &mut MCSTPNode) {
+        loop {
+            node.reset();
+
+            // set myself as the last node
+            let ptr = node as *mut MCSTPNode;
+            let prev = self.last.swap(ptr, Ordering::AcqRel);
+            if prev.is_null() {
+                return;
+            }
+
+            (*prev).next.store(ptr, Ordering::Release);
+
+            if self.wait(node) {
+                return;
+            }
+        }
+    }
+
+    /// Release the lock acquired by `lock_raw` or `try_lock_raw`.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held with `node`.
+    #[inline(always)]
+    pub unsafe fn unlock_raw(&self, node: &MCSTPNode) {
+        let Some(mut next) = self.next_of(node) else {
+            return;
+        };
+
+        loop {
+            let succ = &*next;
+
+            // hand the lock over if the successor published the time recently
+            let elapsed = crate::uptime().saturating_sub(succ.time.load(Ordering::Relaxed));
+            if elapsed <= self.threshold {
+                succ.status.store(GRANTED, Ordering::Release);
+                return;
+            }
+
+            // skip the successor, which looks preempted
+            succ.status.store(REMOVED, Ordering::Relaxed);
+            let after = self.next_of(succ);
+            succ.status.store(RECYCLED, Ordering::Release);
+
+            match after {
+                Some(after) => next = after,
+                None => return,
+            }
+        }
+    }
+
+    /// Return a pointer to the protected data.
+    #[inline(always)]
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    /// Spin and publish the time until the lock is handed over.
+    ///
+    /// Return `false` if the waiter is skipped and must queue up again.
+    #[inline(always)]
+    fn wait(&self, node: &MCSTPNode) -> bool {
+        loop {
+            match node.status.load(Ordering::Acquire) {
+                GRANTED => return true,
+                REMOVED | RECYCLED => break,
+                _ => {
+                    node.time.store(crate::uptime(), Ordering::Relaxed);
+                    hint::spin_loop();
+                }
+            }
+        }
+
+        // the releaser may be still reading the node
+        while node.status.load(Ordering::Acquire) != RECYCLED {
+            hint::spin_loop();
+        }
+
+        false
+    }
+
+    /// Return the successor of `node`, or `None` if `node` is the last node.
+    /// If `node` is the last node, the lock is released.
+    #[inline(always)]
+    fn next_of(&self, node: &MCSTPNode) -> Option<*mut MCSTPNode> {
+        let ptr = node as *const MCSTPNode as *mut MCSTPNode;
+
+        if node.next.load(Ordering::Relaxed).is_null() {
+            if self
+                .last
+                .compare_exchange(ptr, null_mut(), Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return None;
+            }
+
+            // other thread is entering lock and wait the execution
+            crate::mwait::wait_while_null(&node.next);
+        }
+
+        Some(node.next.load(Ordering::Acquire))
+    }
+}
+
+pub struct MCSTPLockGuard<'a, T: Send> {
+    node: &'a mut MCSTPNode,
+    mcs_tp_lock: &'a MCSTPLock<T>,
+    access: WriteAccess<T>,
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<'a, T: Send> MCSTPLockGuard<'a, T> {
+    #[inline(always)]
+    fn new(
+        mcs_tp_lock: &'a MCSTPLock<T>,
+        node: &'a mut MCSTPNode,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        MCSTPLockGuard {
+            node,
+            mcs_tp_lock,
+            access: WriteAccess::start(&mcs_tp_lock.data),
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Send> Drop for MCSTPLockGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.access.end();
+        unsafe { self.mcs_tp_lock.unlock_raw(self.node) };
+    }
+}
+
+impl<T: Send> Deref for MCSTPLockGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.access.get(&self.mcs_tp_lock.data) }
+    }
+}
+
+impl<T: Send> DerefMut for MCSTPLockGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.access.get(&self.mcs_tp_lock.data) }
+    }
+}
+
+impl<T: Send> AsMut<T> for MCSTPLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.access.get(&self.mcs_tp_lock.data) }
+    }
+}
+
+impl<T: Send> AsRef<T> for MCSTPLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &T {
+        unsafe { &*self.access.get(&self.mcs_tp_lock.data) }
+    }
+}
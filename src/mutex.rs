@@ -1,40 +1,159 @@
 //! # Mutex and LockGuard Types
 //!
 //! The `Mutex` and `LockGuard` types in this module provide a way to manage concurrent access to shared resources.
-//! These types have different implementations depending on whether the `std` feature is enabled or not.
-//! When the `std` feature is enabled, it uses `parking_lot::Mutex` for efficient locking.
-//! When the `std` feature is disabled, it falls back to using `super::mcs::MCSLock`.
+//! `Mutex<T, L>` is generic over the raw lock `L`, which implements `RawLock`.
+//! The default raw lock `DefaultLock` depends on the features.
+//! When the `std` feature is enabled, it is `parking_lot::RawMutex` for efficient locking.
+//! When the `std` feature is disabled, it falls back to `super::mcs::MCSLock`,
+//! or `super::spinlock::SpinLock` if the `spinlock` feature is enabled.
+//!
+//! Another raw lock can be chosen for each mutex,
+//! e.g. `super::ticket::TicketLock` for a fair lock without nodes,
+//! or `super::qspinlock::QSpinLock` for a fair lock in 4 bytes.
+//!
+//! ```
+//! use awkernel_sync::{
+//!     mutex::{MCSNode, Mutex},
+//!     spinlock::SpinLock,
+//! };
+//!
+//! let data = Mutex::<_, SpinLock<()>>::with_raw_lock(0);
+//!
+//! let mut node = MCSNode::new();
+//! *data.lock(&mut node) += 1;
+//! ```
 
-#[cfg(all(not(feature = "std"), not(feature = "spinlock")))]
-type Lock<T> = super::mcs::MCSLock<T>;
+#[cfg(not(loom))]
+use crate::mcs::node_pool::PooledNode;
+#[cfg(not(loom))]
+use alloc::sync::Arc;
+use core::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
 
-#[cfg(all(not(feature = "std"), feature = "spinlock"))]
-type Lock<T> = super::spinlock::SpinLock<T>;
+#[cfg(not(loom))]
+use core::cell::UnsafeCell;
 
+#[cfg(loom)]
+use loom::cell::UnsafeCell;
+
+use crate::access::WriteAccess;
+
+pub use super::mcs::{MCSNode, MCSNodeArray};
+
+/// The raw lock of `Mutex` selected by the features.
 #[cfg(all(not(feature = "std"), not(feature = "spinlock")))]
-pub type LockGuard<'a, T> = super::mcs::MCSLockGuard<'a, T>;
+pub type DefaultLock = super::mcs::MCSLock<()>;
 
+/// The raw lock of `Mutex` selected by the features.
 #[cfg(all(not(feature = "std"), feature = "spinlock"))]
-pub type LockGuard<'a, T> = super::spinlock::SpinLockGuard<'a, T>;
+pub type DefaultLock = super::spinlock::SpinLock<()>;
 
+/// The raw lock of `Mutex` selected by the features.
 #[cfg(feature = "std")]
-type Lock<T> = parking_lot::Mutex<T>;
+pub type DefaultLock = parking_lot::RawMutex;
+
+pub type LockGuard<'a, T> = MutexGuard<'a, T>;
+
+/// A raw lock without data, used by `Mutex`.
+///
+/// Every method takes a node so that queue locks like `MCSLock<()>` can be used.
+/// Other locks ignore it.
+///
+/// # Safety
+///
+/// Only one holder can hold the lock at a time.
+/// Acquiring the lock must synchronize with the previous release.
+pub unsafe trait RawLock {
+    /// The unlocked state.
+    #[cfg(not(loom))]
+    const INIT: Self;
+
+    /// Make the unlocked state.
+    #[cfg(loom)]
+    fn new() -> Self;
+
+    /// `true` if the lock is queued by nodes.
+    /// Otherwise, `Mutex` does not borrow a node from the per-CPU node pool.
+    const USES_NODE: bool;
+
+    /// Acquire the lock.
+    ///
+    /// # Safety
+    ///
+    /// `node` must not be moved or dropped until `unlock` is called with it.
+    unsafe fn lock(&self, node: &mut MCSNode);
+
+    /// Try to acquire the lock without waiting.
+    ///
+    /// # Safety
+    ///
+    /// If this returns `true`, `node` must not be moved or dropped
+    /// until `unlock` is called with it.
+    unsafe fn try_lock(&self, node: &mut MCSNode) -> bool;
+
+    /// Release the lock.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held with `node`.
+    unsafe fn unlock(&self, node: &MCSNode);
+}
+
+/// A raw lock that can be released on a CPU other than the one that acquired it.
+///
+/// `Mutex::lock_arc` requires this because its guard can be sent to another CPU.
+///
+/// # Safety
+///
+/// `unlock` must release the lock on any CPU if it is given the node given to `lock`.
+pub unsafe trait RawLockSend: RawLock {}
+
+#[cfg(feature = "std")]
+unsafe impl RawLock for parking_lot::RawMutex {
+    #[cfg(not(loom))]
+    const INIT: Self = <Self as parking_lot::lock_api::RawMutex>::INIT;
+
+    #[cfg(loom)]
+    fn new() -> Self {
+        <Self as parking_lot::lock_api::RawMutex>::INIT
+    }
+
+    const USES_NODE: bool = false;
+
+    #[inline(always)]
+    unsafe fn lock(&self, _node: &mut MCSNode) {
+        parking_lot::lock_api::RawMutex::lock(self);
+    }
+
+    #[inline(always)]
+    unsafe fn try_lock(&self, _node: &mut MCSNode) -> bool {
+        parking_lot::lock_api::RawMutex::try_lock(self)
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self, _node: &MCSNode) {
+        parking_lot::lock_api::RawMutex::unlock(self);
+    }
+}
 
 #[cfg(feature = "std")]
-pub type LockGuard<'a, T> = parking_lot::MutexGuard<'a, T>;
+unsafe impl RawLockSend for parking_lot::RawMutex {}
 
 /// A mutual exclusion primitive that provides safe concurrent access to the inner data.
 ///
 /// The `Mutex` type can be used to ensure that only one thread can access the data at a time.
 ///
-/// It has different implementations depending on whether the `std` feature is enabled or not.
-/// When the `std` feature is enabled, it uses `parking_lot::Mutex` for efficient locking.
+/// The data is protected by the raw lock `L`, which is `DefaultLock` selected by the features
+/// unless another one is given.
+/// When the `std` feature is enabled, it uses `parking_lot::RawMutex` for efficient locking.
 /// When the `std` feature is disabled, it falls back to using `super::mcs::MCSLock`.
 ///
 /// # Example
 ///
 /// ```
-/// use awkernel_lib::sync::mutex::{MCSNode, Mutex};
+/// use awkernel_sync::mutex::{MCSNode, Mutex};
 /// use std::{thread, sync::Arc};
 ///
 /// let data = Arc::new(Mutex::new(0));
@@ -57,53 +176,514 @@ pub type LockGuard<'a, T> = parking_lot::MutexGuard<'a, T>;
 /// let mut node = MCSNode::new();
 /// assert_eq!(*data.lock(&mut node), 10);
 /// ```
-pub struct Mutex<T: Send> {
-    #[cfg(not(std))]
-    mutex: Lock<T>,
+pub struct Mutex<T: Send, L: RawLock = DefaultLock> {
+    raw: L,
+    data: UnsafeCell<T>,
 }
 
+unsafe impl<T: Send, L: RawLock> Sync for Mutex<T, L> {}
+unsafe impl<T: Send, L: RawLock> Send for Mutex<T, L> {}
+
 impl<T: Send> Mutex<T> {
+    #[cfg(not(loom))]
     pub const fn new(v: T) -> Self {
+        Mutex::with_raw_lock(v)
+    }
+
+    #[cfg(loom)]
+    pub fn new(v: T) -> Self {
+        Mutex::with_raw_lock(v)
+    }
+}
+
+impl<T: Send, L: RawLock> Mutex<T, L> {
+    /// Make a mutex protected by the raw lock `L`.
+    #[cfg(not(loom))]
+    pub const fn with_raw_lock(v: T) -> Self {
         Self {
-            mutex: Lock::new(v),
+            raw: L::INIT,
+            data: UnsafeCell::new(v),
         }
     }
 
-    #[cfg(all(not(feature = "std"), not(feature = "spinlock")))]
+    /// Make a mutex protected by the raw lock `L`.
+    #[cfg(loom)]
+    pub fn with_raw_lock(v: T) -> Self {
+        Self {
+            raw: L::new(),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    /// Acquire the lock.
+    ///
+    /// `node` is used only if `L` is a queue lock like `MCSLock<()>`.
+    #[cfg(any(not(feature = "std"), loom))]
+    #[inline(always)]
+    pub fn lock<'a>(&'a self, node: &'a mut MCSNode) -> MutexGuard<'a, T, L> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.lock_guard(MutexGuard::new(self, Some(node), _interrupt_guard))
+    }
+
+    /// Acquire the lock.
+    ///
+    /// With `std`, `node` is not used and may be dropped while the guard is alive.
+    /// If `L` uses nodes, a node is borrowed from the per-CPU node pool as `lock_pooled` does.
+    #[cfg(all(feature = "std", not(loom)))]
+    #[inline(always)]
+    pub fn lock<'a>(&'a self, _node: &mut MCSNode) -> MutexGuard<'a, T, L> {
+        self.lock_pooled()
+    }
+
+    /// Acquire the lock without giving a node.
+    ///
+    /// If `L` uses nodes, a node is borrowed from the per-CPU node pool.
+    /// See `MCSLock::lock_pooled`.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub fn lock_pooled(&self) -> MutexGuard<'_, T, L> {
+        // interrupts must be disabled before borrowing a per-CPU node
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.lock_guard(MutexGuard::pooled(self, _interrupt_guard))
+    }
+
+    /// Try to acquire the lock.
+    #[cfg(any(not(feature = "std"), loom))]
+    #[inline(always)]
+    pub fn try_lock<'a>(&'a self, node: &'a mut MCSNode) -> Option<MutexGuard<'a, T, L>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.try_lock_guard(MutexGuard::new(self, Some(node), _interrupt_guard))
+    }
+
+    /// Try to acquire the lock.
+    ///
+    /// With `std`, `node` is not used as in `lock`.
+    #[cfg(all(feature = "std", not(loom)))]
     #[inline(always)]
-    pub fn lock<'a>(&'a self, node: &'a mut MCSNode<T>) -> LockGuard<'a, T> {
-        self.mutex.lock(node)
+    pub fn try_lock<'a>(&'a self, _node: &mut MCSNode) -> Option<MutexGuard<'a, T, L>> {
+        self.try_lock_pooled()
     }
 
-    #[cfg(all(not(feature = "std"), feature = "spinlock"))]
+    /// Try to acquire the lock without giving a node.
+    ///
+    /// If `L` uses nodes, a node is borrowed from the per-CPU node pool.
+    /// See `MCSLock::try_lock_pooled`.
+    #[cfg(not(loom))]
     #[inline(always)]
-    pub fn lock<'a>(&'a self, _node: &'a mut MCSNode<T>) -> LockGuard<'a, T> {
-        self.mutex.lock()
+    pub fn try_lock_pooled(&self) -> Option<MutexGuard<'_, T, L>> {
+        // interrupts must be disabled before borrowing a per-CPU node
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.try_lock_guard(MutexGuard::pooled(self, _interrupt_guard))
     }
 
-    #[cfg(feature = "std")]
+    /// Call `f` with the data while holding the lock.
+    ///
+    /// The node is made on the stack and lives until `f` returns.
+    ///
+    /// ```
+    /// use awkernel_sync::mutex::Mutex;
+    ///
+    /// let data = Mutex::new(0);
+    /// data.with_lock(|data| *data += 1);
+    /// assert_eq!(data.with_lock(|data| *data), 1);
+    /// ```
     #[inline(always)]
-    pub fn lock<'a>(&'a self, _node: &mut MCSNode<T>) -> LockGuard<'a, T> {
-        self.mutex.lock()
+    pub fn with_lock<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut node = MCSNode::new();
+        let mut guard = self.lock(&mut node);
+        f(&mut guard)
     }
 
-    #[cfg(all(not(feature = "std"), not(feature = "spinlock")))]
+    /// Call `f` with the data if the lock is acquired without waiting.
+    /// Otherwise, return `None` without calling `f`.
     #[inline(always)]
-    pub fn try_lock<'a>(&'a self, node: &'a mut MCSNode<T>) -> Option<LockGuard<'a, T>> {
-        self.mutex.try_lock(node)
+    pub fn try_with_lock<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut node = MCSNode::new();
+        let mut guard = self.try_lock(&mut node)?;
+        Some(f(&mut guard))
     }
 
-    #[cfg(all(not(feature = "std"), feature = "spinlock"))]
     #[inline(always)]
-    pub fn try_lock<'a>(&'a self, _node: &'a mut MCSNode<T>) -> Option<LockGuard<'a, T>> {
-        self.mutex.try_lock()
+    fn lock_guard<'a>(&'a self, mut guard: MutexGuard<'a, T, L>) -> MutexGuard<'a, T, L> {
+        match guard.node.as_deref_mut() {
+            Some(node) => unsafe { self.raw.lock(node) },
+            None => unsafe { self.raw.lock(&mut MCSNode::new()) },
+        }
+
+        guard.access = WriteAccess::start(&self.data);
+        guard
+    }
+
+    #[inline(always)]
+    fn try_lock_guard<'a>(
+        &'a self,
+        mut guard: MutexGuard<'a, T, L>,
+    ) -> Option<MutexGuard<'a, T, L>> {
+        let locked = match guard.node.as_deref_mut() {
+            Some(node) => unsafe { self.raw.try_lock(node) },
+            None => unsafe { self.raw.try_lock(&mut MCSNode::new()) },
+        };
+
+        if locked {
+            guard.access = WriteAccess::start(&self.data);
+            Some(guard)
+        } else {
+            guard.need_unlock = false;
+            None
+        }
     }
 
-    #[cfg(feature = "std")]
+    /// Acquire the lock through an `Arc`.
+    ///
+    /// The returned guard owns a clone of the `Arc` and has no lifetime,
+    /// so it can be stored in a struct, held across `await` or sent to another thread.
+    /// If `L` uses nodes, the node is allocated on the heap and the guard owns it.
+    ///
+    /// Interrupts are disabled only while waiting for the lock.
+    /// The guard does not keep them disabled because it may be dropped on another CPU,
+    /// so a mutex locked by `lock_arc` must not be locked by interrupt handlers.
+    #[cfg(not(loom))]
     #[inline(always)]
-    pub fn try_lock<'a>(&'a self, _node: &mut MCSNode<T>) -> Option<LockGuard<'a, T>> {
-        self.mutex.try_lock()
+    pub fn lock_arc(self: &Arc<Self>) -> ArcLockGuard<T, L>
+    where
+        L: RawLockSend,
+    {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+
+        let node = HeapNode(if L::USES_NODE {
+            alloc::boxed::Box::into_raw(alloc::boxed::Box::new(MCSNode::new()))
+        } else {
+            core::ptr::null_mut()
+        });
+
+        match unsafe { node.0.as_mut() } {
+            Some(node) => unsafe { self.raw.lock(node) },
+            None => unsafe { self.raw.lock(&mut MCSNode::new()) },
+        }
+
+        ArcLockGuard {
+            mutex: self.clone(),
+            node,
+        }
     }
 }
 
-pub use super::mcs::MCSNode;
+pub struct MutexGuard<'a, T: Send, L: RawLock = DefaultLock> {
+    mutex: &'a Mutex<T, L>,
+
+    /// `None` if `L` does not use nodes and no node is given.
+    node: Option<&'a mut MCSNode>,
+
+    need_unlock: bool,
+    access: WriteAccess<T>,
+
+    /// The node borrowed from the per-CPU pool.
+    /// This must be dropped before `_interrupt_guard`.
+    #[cfg(not(loom))]
+    _pooled: Option<PooledNode>,
+
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<'a, T: Send, L: RawLock> MutexGuard<'a, T, L> {
+    #[inline(always)]
+    fn new(
+        mutex: &'a Mutex<T, L>,
+        node: Option<&'a mut MCSNode>,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        MutexGuard {
+            mutex,
+            node,
+            need_unlock: true,
+            access: WriteAccess::idle(),
+            #[cfg(not(loom))]
+            _pooled: None,
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Make a guard with a node borrowed from the per-CPU pool if `L` uses nodes.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    fn pooled(
+        mutex: &'a Mutex<T, L>,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        if !L::USES_NODE {
+            return MutexGuard::new(mutex, None, _interrupt_guard);
+        }
+
+        let pooled = PooledNode::new();
+        let mut guard =
+            MutexGuard::new(mutex, Some(unsafe { &mut *pooled.get() }), _interrupt_guard);
+        guard._pooled = Some(pooled);
+        guard
+    }
+
+    /// Make a guard for a part of the locked data.
+    ///
+    /// The lock is held until the returned guard is dropped.
+    /// This is an associated function to avoid conflicts with methods of `T`.
+    ///
+    /// ```
+    /// use awkernel_sync::mutex::{MCSNode, Mutex, MutexGuard};
+    ///
+    /// let lock = Mutex::new((0, 0));
+    /// let mut node = MCSNode::new();
+    ///
+    /// let mut second = MutexGuard::map(lock.lock(&mut node), |data| &mut data.1);
+    /// *second = 1;
+    /// ```
+    #[inline(always)]
+    pub fn map<U, F>(mut guard: Self, f: F) -> MappedMutexGuard<'a, T, U, L>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(&mut guard) as *mut U;
+        MappedMutexGuard {
+            _guard: guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<U, F>(mut guard: Self, f: F) -> Result<MappedMutexGuard<'a, T, U, L>, Self>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(&mut guard).map(|data| data as *mut U) {
+            Some(data) => Ok(MappedMutexGuard {
+                _guard: guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<U, E, F>(
+        mut guard: Self,
+        f: F,
+    ) -> Result<MappedMutexGuard<'a, T, U, L>, (Self, E)>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> Result<&mut U, E>,
+    {
+        match f(&mut guard).map(|data| data as *mut U) {
+            Ok(data) => Ok(MappedMutexGuard {
+                _guard: guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
+impl<T: Send, L: RawLock> MutexGuard<'_, T, L> {
+    #[cfg(loom)]
+    pub fn with_mut<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(*mut T) -> R,
+    {
+        f(self.access.get(&self.mutex.data))
+    }
+}
+
+impl<T: Send, L: RawLock> Drop for MutexGuard<'_, T, L> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if !self.need_unlock {
+            return;
+        }
+
+        self.access.end();
+        match self.node.as_deref() {
+            Some(node) => unsafe { self.mutex.raw.unlock(node) },
+            None => unsafe { self.mutex.raw.unlock(&MCSNode::new()) },
+        }
+    }
+}
+
+impl<T: Send, L: RawLock> Deref for MutexGuard<'_, T, L> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.access.get(&self.mutex.data) }
+    }
+}
+
+impl<T: Send, L: RawLock> DerefMut for MutexGuard<'_, T, L> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.access.get(&self.mutex.data) }
+    }
+}
+
+impl<T: Send, L: RawLock> AsMut<T> for MutexGuard<'_, T, L> {
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.access.get(&self.mutex.data) }
+    }
+}
+
+impl<T: Send, L: RawLock> AsRef<T> for MutexGuard<'_, T, L> {
+    fn as_ref(&self) -> &T {
+        unsafe { &*self.access.get(&self.mutex.data) }
+    }
+}
+
+/// A guard for a part of the data protected by `Mutex`,
+/// made by `MutexGuard::map`, `filter_map` or `try_map`.
+///
+/// The lock and the interrupt guard are held by the original guard inside.
+pub struct MappedMutexGuard<'a, T: Send, U: ?Sized, L: RawLock = DefaultLock> {
+    _guard: MutexGuard<'a, T, L>,
+    data: *mut U,
+}
+
+impl<'a, T: Send, U: ?Sized, L: RawLock> MappedMutexGuard<'a, T, U, L> {
+    /// Make a guard for a part of the mapped data.
+    #[inline(always)]
+    pub fn map<V, F>(guard: Self, f: F) -> MappedMutexGuard<'a, T, V, L>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> &mut V,
+    {
+        let data = f(unsafe { &mut *guard.data }) as *mut V;
+        MappedMutexGuard {
+            _guard: guard._guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<V, F>(guard: Self, f: F) -> Result<MappedMutexGuard<'a, T, V, L>, Self>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> Option<&mut V>,
+    {
+        match f(unsafe { &mut *guard.data }).map(|data| data as *mut V) {
+            Some(data) => Ok(MappedMutexGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<V, E, F>(guard: Self, f: F) -> Result<MappedMutexGuard<'a, T, V, L>, (Self, E)>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> Result<&mut V, E>,
+    {
+        match f(unsafe { &mut *guard.data }).map(|data| data as *mut V) {
+            Ok(data) => Ok(MappedMutexGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
+impl<T: Send, U: ?Sized, L: RawLock> Deref for MappedMutexGuard<'_, T, U, L> {
+    type Target = U;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<T: Send, U: ?Sized, L: RawLock> DerefMut for MappedMutexGuard<'_, T, U, L> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data }
+    }
+}
+
+/// A guard made by `Mutex::lock_arc`, which keeps the `Mutex` alive by an `Arc`.
+///
+/// Unlike `MutexGuard`, this is `Send` and does not keep interrupts disabled.
+#[cfg(not(loom))]
+pub struct ArcLockGuard<T: Send, L: RawLock = DefaultLock> {
+    mutex: Arc<Mutex<T, L>>,
+    node: HeapNode,
+}
+
+/// The node of `ArcLockGuard` on the heap, or null if the raw lock does not use nodes.
+///
+/// This is a raw pointer rather than a `Box` because the lock keeps pointers to the node.
+#[cfg(not(loom))]
+struct HeapNode(*mut MCSNode);
+
+// The node is accessed only by the lock and the guard owning it.
+#[cfg(not(loom))]
+unsafe impl Send for HeapNode {}
+
+#[cfg(not(loom))]
+impl Drop for HeapNode {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            drop(unsafe { alloc::boxed::Box::from_raw(self.0) });
+        }
+    }
+}
+
+#[cfg(not(loom))]
+impl<T: Send, L: RawLock> ArcLockGuard<T, L> {
+    /// Return the `Arc` of the locked `Mutex`.
+    #[inline(always)]
+    pub fn mutex(guard: &Self) -> &Arc<Mutex<T, L>> {
+        &guard.mutex
+    }
+}
+
+#[cfg(not(loom))]
+impl<T: Send, L: RawLock> Drop for ArcLockGuard<T, L> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        // the node is freed after this.
+        match unsafe { self.node.0.as_ref() } {
+            Some(node) => unsafe { self.mutex.raw.unlock(node) },
+            None => unsafe { self.mutex.raw.unlock(&MCSNode::new()) },
+        }
+    }
+}
+
+#[cfg(not(loom))]
+impl<T: Send, L: RawLock> Deref for ArcLockGuard<T, L> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+#[cfg(not(loom))]
+impl<T: Send, L: RawLock> DerefMut for ArcLockGuard<T, L> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
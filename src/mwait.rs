@@ -52,6 +52,41 @@ mod x86_mwait {
         }
     }
 
+    /// Wait while the value at the given address is equal to `true`.
+    fn wait_while_true_mwait(val: &AtomicBool) {
+        let addr = val.as_ptr();
+
+        unsafe {
+            while val.load(Ordering::Relaxed) {
+                asm!("monitor", in("rax") addr, in("rcx") 0, in("edx") 0);
+                if !val.load(Ordering::Relaxed) {
+                    break;
+                }
+                asm!("mwait", in("rax") 0, in("rcx") 0);
+            }
+        }
+    }
+
+    /// Wait while the value at the given address is equal to `true`.
+    #[inline(always)]
+    pub(crate) fn wait_while_true(val: &AtomicBool) {
+        let supported = MWAIT_SUPPORTED.load(Ordering::Relaxed);
+
+        if supported == NOT_INITIALIZED {
+            if has_monitor_mwait() {
+                MWAIT_SUPPORTED.store(SUPPORTED, Ordering::Relaxed);
+                wait_while_true_mwait(val);
+            } else {
+                MWAIT_SUPPORTED.store(NOT_SUPPORTED, Ordering::Relaxed);
+                super::wait_while_true_spin(val);
+            }
+        } else if supported == SUPPORTED {
+            wait_while_true_mwait(val);
+        } else if supported == NOT_SUPPORTED {
+            super::wait_while_true_spin(val);
+        }
+    }
+
     /// Wait while the value at the given address is equal to `current`.
     #[inline(always)]
     fn wait_while_equal_mwait(val: &AtomicUsize, current: usize, ordering: Ordering) {
@@ -161,6 +196,53 @@ fn wait_while_false_spin(val: &AtomicBool) {
     }
 }
 
+/// Wait while the value at the given address is equal to `true`.
+#[cfg(not(feature = "x86_mwait"))]
+#[inline(always)]
+pub(crate) fn wait_while_true(val: &AtomicBool) {
+    wait_while_true_spin(val);
+}
+
+/// Wait while the value at the given address is equal to `true`.
+#[cfg(feature = "x86_mwait")]
+#[inline(always)]
+pub(crate) fn wait_while_true(val: &AtomicBool) {
+    x86_mwait::wait_while_true(val);
+}
+
+#[inline(always)]
+fn wait_while_true_spin(val: &AtomicBool) {
+    while val.load(Ordering::Relaxed) {
+        hint::spin_loop();
+
+        #[cfg(loom)]
+        loom::thread::yield_now();
+    }
+}
+
+/// Wait while the value at the given address is equal to `false` and `cancel` returns `false`.
+///
+/// Return `true` if the value became `true`, and `false` if the wait was cancelled.
+/// Monitor/MWAIT cannot be used here because `cancel` must be polled.
+#[inline(always)]
+pub(crate) fn wait_while_false_until<F>(val: &AtomicBool, mut cancel: F) -> bool
+where
+    F: FnMut() -> bool,
+{
+    while !val.load(Ordering::Relaxed) {
+        if cancel() {
+            return false;
+        }
+
+        hint::spin_loop();
+
+        #[cfg(loom)]
+        loom::thread::yield_now();
+    }
+
+    true
+}
+
 /// Wait while the value at the given address is equal to `current`.
 #[cfg(not(feature = "x86_mwait"))]
 #[inline(always)]
@@ -185,6 +267,33 @@ fn wait_while_equal_spin(val: &AtomicUsize, current: usize, ordering: Ordering)
     }
 }
 
+/// Wait while the value at the given address is equal to `current` and `cancel` returns `false`.
+///
+/// Return `true` if the value changed, and `false` if the wait was cancelled.
+#[inline(always)]
+pub(crate) fn wait_while_equal_until<F>(
+    val: &AtomicUsize,
+    current: usize,
+    ordering: Ordering,
+    mut cancel: F,
+) -> bool
+where
+    F: FnMut() -> bool,
+{
+    while val.load(ordering) == current {
+        if cancel() {
+            return false;
+        }
+
+        hint::spin_loop();
+
+        #[cfg(loom)]
+        loom::thread::yield_now();
+    }
+
+    true
+}
+
 /// Wait while the value at the given address is null.
 #[cfg(not(feature = "x86_mwait"))]
 #[inline(always)]
@@ -0,0 +1,446 @@
+//! # Poisoning locks
+//!
+//! The locks in this module wrap the locks of this crate
+//! and are poisoned when a guard is dropped while the task is unwinding from a panic.
+//! After that, acquiring the lock returns `Err(PoisonError)`,
+//! which still holds the guard so that the data can be inspected or repaired.
+//!
+//! Whether the task is unwinding is given by the function registered by `crate::set_panicking_fn`.
+//! Only writers poison `RwLock`.
+//!
+//! ```
+//! use awkernel_sync::{
+//!     mutex::MCSNode,
+//!     poison::{Mutex, PoisonError},
+//! };
+//! use std::{sync::Arc, thread};
+//!
+//! let data = Arc::new(Mutex::new(0));
+//!
+//! let data2 = data.clone();
+//! let _ = thread::spawn(move || {
+//!     let mut node = MCSNode::new();
+//!     let _guard = data2.lock(&mut node).unwrap();
+//!     panic!();
+//! })
+//! .join();
+//!
+//! let mut node = MCSNode::new();
+//! let guard = data.lock(&mut node).unwrap_or_else(PoisonError::into_inner);
+//! assert_eq!(*guard, 0);
+//! drop(guard);
+//!
+//! data.clear_poison();
+//! assert!(!data.is_poisoned());
+//! ```
+
+use core::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    mcs::MCSNode,
+    mutex::{self, DefaultLock, RawLock},
+    rwlock::{self, DefaultPolicy, RawRwLock},
+    spinlock,
+};
+
+/// An error returned when a lock is poisoned.
+///
+/// The lock is held by the guard inside.
+pub struct PoisonError<G> {
+    guard: G,
+}
+
+impl<G> PoisonError<G> {
+    /// Return the guard to access the data regardless of the poison.
+    #[inline(always)]
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+
+    #[inline(always)]
+    pub fn get_ref(&self) -> &G {
+        &self.guard
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+
+impl<G> fmt::Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<G> fmt::Display for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("poisoned lock: another task panicked while holding it")
+    }
+}
+
+impl<G> core::error::Error for PoisonError<G> {}
+
+/// An error returned by `try_lock` and the like.
+pub enum TryLockError<G> {
+    /// The lock is acquired, but it is poisoned.
+    Poisoned(PoisonError<G>),
+
+    /// The lock is held by another.
+    WouldBlock,
+}
+
+impl<G> From<PoisonError<G>> for TryLockError<G> {
+    #[inline(always)]
+    fn from(err: PoisonError<G>) -> Self {
+        TryLockError::Poisoned(err)
+    }
+}
+
+impl<G> fmt::Debug for TryLockError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::Poisoned(err) => f.debug_tuple("Poisoned").field(err).finish(),
+            TryLockError::WouldBlock => f.write_str("WouldBlock"),
+        }
+    }
+}
+
+impl<G> fmt::Display for TryLockError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::Poisoned(err) => err.fmt(f),
+            TryLockError::WouldBlock => f.write_str("the lock is held by another"),
+        }
+    }
+}
+
+impl<G> core::error::Error for TryLockError<G> {}
+
+pub type LockResult<G> = Result<G, PoisonError<G>>;
+
+pub type TryLockResult<G> = Result<G, TryLockError<G>>;
+
+/// The poison flag of a lock.
+struct Flag(AtomicBool);
+
+impl Flag {
+    const fn new() -> Self {
+        Flag(AtomicBool::new(false))
+    }
+
+    /// Check the flag after acquiring the lock.
+    /// `guard` is made whether the lock is poisoned or not.
+    #[inline(always)]
+    fn check<G>(&self, guard: G) -> LockResult<G> {
+        if self.get() {
+            Err(PoisonError { guard })
+        } else {
+            Ok(guard)
+        }
+    }
+
+    #[inline(always)]
+    fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    fn set(&self, poisoned: bool) {
+        self.0.store(poisoned, Ordering::Relaxed);
+    }
+}
+
+/// Records whether the holder was already unwinding when it acquired the lock,
+/// so that a lock acquired during unwinding is not poisoned by the same panic.
+struct PoisonOnUnwind<'a> {
+    flag: &'a Flag,
+    panicking: bool,
+}
+
+impl<'a> PoisonOnUnwind<'a> {
+    #[inline(always)]
+    fn new(flag: &'a Flag) -> Self {
+        PoisonOnUnwind {
+            flag,
+            panicking: crate::panicking(),
+        }
+    }
+}
+
+impl Drop for PoisonOnUnwind<'_> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if !self.panicking && crate::panicking() {
+            self.flag.set(true);
+        }
+    }
+}
+
+/// `mutex::Mutex` with poisoning.
+pub struct Mutex<T: Send, L: RawLock = DefaultLock> {
+    mutex: mutex::Mutex<T, L>,
+    poison: Flag,
+}
+
+impl<T: Send> Mutex<T> {
+    pub const fn new(v: T) -> Self {
+        Mutex::with_raw_lock(v)
+    }
+}
+
+impl<T: Send, L: RawLock> Mutex<T, L> {
+    /// Make a mutex protected by the raw lock `L`.
+    pub const fn with_raw_lock(v: T) -> Self {
+        Mutex {
+            mutex: mutex::Mutex::with_raw_lock(v),
+            poison: Flag::new(),
+        }
+    }
+
+    /// Acquire the lock.
+    ///
+    /// Return `Err` if the lock is poisoned, but the lock is held in both cases.
+    #[inline(always)]
+    pub fn lock<'a>(&'a self, node: &'a mut MCSNode) -> LockResult<MutexGuard<'a, T, L>> {
+        let guard = self.mutex.lock(node);
+        self.poison.check(MutexGuard::new(guard, &self.poison))
+    }
+
+    /// Acquire the lock without giving a node.
+    /// See `mutex::Mutex::lock_pooled`.
+    #[inline(always)]
+    pub fn lock_pooled(&self) -> LockResult<MutexGuard<'_, T, L>> {
+        let guard = self.mutex.lock_pooled();
+        self.poison.check(MutexGuard::new(guard, &self.poison))
+    }
+
+    /// Try to acquire the lock.
+    #[inline(always)]
+    pub fn try_lock<'a>(&'a self, node: &'a mut MCSNode) -> TryLockResult<MutexGuard<'a, T, L>> {
+        let guard = self.mutex.try_lock(node).ok_or(TryLockError::WouldBlock)?;
+        Ok(self.poison.check(MutexGuard::new(guard, &self.poison))?)
+    }
+
+    /// Return `true` if a holder panicked.
+    #[inline(always)]
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Clear the poison after the data is repaired.
+    #[inline(always)]
+    pub fn clear_poison(&self) {
+        self.poison.set(false);
+    }
+}
+
+pub struct MutexGuard<'a, T: Send, L: RawLock = DefaultLock> {
+    // dropped first, so the lock is poisoned before it is released
+    _poison: PoisonOnUnwind<'a>,
+    guard: mutex::MutexGuard<'a, T, L>,
+}
+
+impl<'a, T: Send, L: RawLock> MutexGuard<'a, T, L> {
+    #[inline(always)]
+    fn new(guard: mutex::MutexGuard<'a, T, L>, poison: &'a Flag) -> Self {
+        MutexGuard {
+            _poison: PoisonOnUnwind::new(poison),
+            guard,
+        }
+    }
+}
+
+impl<T: Send, L: RawLock> Deref for MutexGuard<'_, T, L> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: Send, L: RawLock> DerefMut for MutexGuard<'_, T, L> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// `rwlock::RwLock` with poisoning.
+///
+/// Readers cannot modify the data, so only writers poison the lock.
+pub struct RwLock<T: Send, P: RawRwLock = DefaultPolicy> {
+    rwlock: rwlock::RwLock<T, P>,
+    poison: Flag,
+}
+
+impl<T: Send> RwLock<T> {
+    pub const fn new(v: T) -> Self {
+        RwLock::with_policy(v)
+    }
+}
+
+impl<T: Send, P: RawRwLock> RwLock<T, P> {
+    /// Make a lock with the policy `P`.
+    pub const fn with_policy(v: T) -> Self {
+        RwLock {
+            rwlock: rwlock::RwLock::with_policy(v),
+            poison: Flag::new(),
+        }
+    }
+
+    /// Acquire the reader lock.
+    #[inline(always)]
+    pub fn read(&self) -> LockResult<rwlock::RwLockReadGuard<'_, T, P>> {
+        self.poison.check(self.rwlock.read())
+    }
+
+    /// Acquire the writer lock.
+    #[inline(always)]
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T, P>> {
+        let guard = self.rwlock.write();
+        self.poison
+            .check(RwLockWriteGuard::new(guard, &self.poison))
+    }
+
+    /// Try to acquire the reader lock.
+    #[inline(always)]
+    pub fn try_read(&self) -> TryLockResult<rwlock::RwLockReadGuard<'_, T, P>> {
+        let guard = self.rwlock.try_read().ok_or(TryLockError::WouldBlock)?;
+        Ok(self.poison.check(guard)?)
+    }
+
+    /// Try to acquire the writer lock.
+    #[inline(always)]
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T, P>> {
+        let guard = self.rwlock.try_write().ok_or(TryLockError::WouldBlock)?;
+        Ok(self
+            .poison
+            .check(RwLockWriteGuard::new(guard, &self.poison))?)
+    }
+
+    /// Return `true` if a writer panicked.
+    #[inline(always)]
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Clear the poison after the data is repaired.
+    #[inline(always)]
+    pub fn clear_poison(&self) {
+        self.poison.set(false);
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: Send, P: RawRwLock = DefaultPolicy> {
+    // dropped first, so the lock is poisoned before it is released
+    _poison: PoisonOnUnwind<'a>,
+    guard: rwlock::RwLockWriteGuard<'a, T, P>,
+}
+
+impl<'a, T: Send, P: RawRwLock> RwLockWriteGuard<'a, T, P> {
+    #[inline(always)]
+    fn new(guard: rwlock::RwLockWriteGuard<'a, T, P>, poison: &'a Flag) -> Self {
+        RwLockWriteGuard {
+            _poison: PoisonOnUnwind::new(poison),
+            guard,
+        }
+    }
+}
+
+impl<T: Send, P: RawRwLock> Deref for RwLockWriteGuard<'_, T, P> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: Send, P: RawRwLock> DerefMut for RwLockWriteGuard<'_, T, P> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// `spinlock::SpinLock` with poisoning.
+pub struct SpinLock<T> {
+    spin_lock: spinlock::SpinLock<T>,
+    poison: Flag,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(v: T) -> Self {
+        SpinLock {
+            spin_lock: spinlock::SpinLock::new(v),
+            poison: Flag::new(),
+        }
+    }
+
+    /// Acquire the lock.
+    ///
+    /// Return `Err` if the lock is poisoned, but the lock is held in both cases.
+    #[inline(always)]
+    pub fn lock(&self) -> LockResult<SpinLockGuard<'_, T>> {
+        let guard = self.spin_lock.lock();
+        self.poison.check(SpinLockGuard::new(guard, &self.poison))
+    }
+
+    /// Try to acquire the lock.
+    #[inline(always)]
+    pub fn try_lock(&self) -> TryLockResult<SpinLockGuard<'_, T>> {
+        let guard = self.spin_lock.try_lock().ok_or(TryLockError::WouldBlock)?;
+        Ok(self.poison.check(SpinLockGuard::new(guard, &self.poison))?)
+    }
+
+    /// Return `true` if a holder panicked.
+    #[inline(always)]
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Clear the poison after the data is repaired.
+    #[inline(always)]
+    pub fn clear_poison(&self) {
+        self.poison.set(false);
+    }
+}
+
+pub struct SpinLockGuard<'a, T> {
+    // dropped first, so the lock is poisoned before it is released
+    _poison: PoisonOnUnwind<'a>,
+    guard: spinlock::SpinLockGuard<'a, T>,
+}
+
+impl<'a, T> SpinLockGuard<'a, T> {
+    #[inline(always)]
+    fn new(guard: spinlock::SpinLockGuard<'a, T>, poison: &'a Flag) -> Self {
+        SpinLockGuard {
+            _poison: PoisonOnUnwind::new(poison),
+            guard,
+        }
+    }
+}
+
+impl<T: Send> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: Send> DerefMut for SpinLockGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
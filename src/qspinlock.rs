@@ -0,0 +1,406 @@
+//! # Queued spinlock
+//!
+//! `QSpinLock` is a fair lock packed into a `u32` like Linux's qspinlock.
+//! The word consists of the following fields.
+//!
+//! ```text
+//!  31            18 17   16 15         8 7          0
+//! +----------------+-------+------------+------------+
+//! |    tail CPU    | index |  pending   |   locked   |
+//! +----------------+-------+------------+------------+
+//! ```
+//!
+//! - locked: 1 while the lock is held.
+//! - pending: 1 while the first waiter spins on the word without a queue node.
+//!   Only bit 8 is set, but the whole byte is tested like the locked byte.
+//! - tail: the last waiter of the MCS queue,
+//!   encoded as the CPU ID plus 1 and the nesting index of its per-CPU node.
+//!
+//! An uncontended acquisition is a single compare-and-swap.
+//! The second contender waits with the pending bit,
+//! and the others wait in an MCS queue of per-CPU nodes,
+//! so that the caller does not need to give a node and only the head of the queue polls the lock word.
+//!
+//! ```
+//! use awkernel_sync::qspinlock::QSpinLock;
+//!
+//! let lock = QSpinLock::new(0);
+//! *lock.lock() += 1;
+//! assert_eq!(*lock.try_lock().unwrap(), 1);
+//! assert_eq!(core::mem::size_of::<QSpinLock<()>>(), 4);
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    hint,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::null_mut,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering},
+};
+
+use crate::{
+    access::WriteAccess,
+    mcs::MCSNode,
+    mutex::{RawLock, RawLockSend},
+    NUM_MAX_CPU,
+};
+
+const LOCKED: u32 = 1;
+const LOCKED_MASK: u32 = 0xff;
+const PENDING: u32 = 1 << 8;
+const PENDING_MASK: u32 = 0xff << 8;
+
+const TAIL_INDEX_OFFSET: u32 = 16;
+const TAIL_INDEX_BITS: u32 = 2;
+const TAIL_INDEX_MASK: u32 = ((1 << TAIL_INDEX_BITS) - 1) << TAIL_INDEX_OFFSET;
+const TAIL_CPU_OFFSET: u32 = TAIL_INDEX_OFFSET + TAIL_INDEX_BITS;
+const TAIL_MASK: u32 = !((1 << TAIL_INDEX_OFFSET) - 1);
+
+/// The number of nodes per CPU.
+/// A CPU can wait in the queues of this number of locks at the same time,
+/// for example when an NMI handler waits for a lock while the interrupted code is waiting for another.
+const NUM_NODES: usize = 1 << TAIL_INDEX_BITS;
+
+/// How many times a contender re-reads the word while the pending bit is being handed over to the locked byte.
+const PENDING_LOOPS: usize = 1;
+
+const _: () = assert!(NUM_MAX_CPU < 1 << (32 - TAIL_CPU_OFFSET));
+
+pub struct QSpinLock<T> {
+    val: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for QSpinLock<T> {}
+unsafe impl<T: Send> Send for QSpinLock<T> {}
+
+struct QNode {
+    next: AtomicPtr<QNode>,
+    locked: AtomicBool,
+}
+
+#[repr(align(64))]
+struct PerCpuNodes {
+    /// The number of nodes in use.
+    count: AtomicUsize,
+    nodes: [QNode; NUM_NODES],
+}
+
+impl PerCpuNodes {
+    const fn new() -> Self {
+        PerCpuNodes {
+            count: AtomicUsize::new(0),
+            nodes: [const {
+                QNode {
+                    next: AtomicPtr::new(null_mut()),
+                    locked: AtomicBool::new(false),
+                }
+            }; NUM_NODES],
+        }
+    }
+}
+
+static NODES: [PerCpuNodes; NUM_MAX_CPU] = [const { PerCpuNodes::new() }; NUM_MAX_CPU];
+
+#[inline(always)]
+fn encode_tail(cpu: usize, index: usize) -> u32 {
+    ((cpu as u32 + 1) << TAIL_CPU_OFFSET) | ((index as u32) << TAIL_INDEX_OFFSET)
+}
+
+#[inline(always)]
+fn decode_tail(tail: u32) -> &'static QNode {
+    let cpu = (tail >> TAIL_CPU_OFFSET) as usize - 1;
+    let index = ((tail & TAIL_INDEX_MASK) >> TAIL_INDEX_OFFSET) as usize;
+    &NODES[cpu].nodes[index]
+}
+
+impl<T> QSpinLock<T> {
+    pub const fn new(v: T) -> Self {
+        QSpinLock {
+            val: AtomicU32::new(0),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    /// acquire lock
+    #[inline(always)]
+    pub fn lock(&self) -> QSpinLockGuard<'_, T> {
+        // interrupts must be disabled before using a per-CPU node
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        unsafe { self.lock_raw() };
+        QSpinLockGuard::new(self, _interrupt_guard)
+    }
+
+    /// Try to acquire the lock without waiting.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<QSpinLockGuard<'_, T>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        if self.try_acquire() {
+            Some(QSpinLockGuard::new(self, _interrupt_guard))
+        } else {
+            None
+        }
+    }
+
+    /// Acquire the lock without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// Interrupts must be disabled while the lock is held or waited for,
+    /// because a waiter uses a node of the current CPU.
+    #[inline(always)]
+    pub unsafe fn lock_raw(&self) {
+        if let Err(val) = self
+            .val
+            .compare_exchange(0, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+        {
+            self.lock_slow(val);
+        }
+    }
+
+    /// Try to acquire the lock without making a guard.
+    ///
+    /// # Safety
+    ///
+    /// Interrupts must be disabled while the lock is held, as `lock_raw` requires.
+    #[inline(always)]
+    pub unsafe fn try_lock_raw(&self) -> bool {
+        self.try_acquire()
+    }
+
+    #[inline(always)]
+    fn try_acquire(&self) -> bool {
+        self.val.load(Ordering::Relaxed) == 0
+            && self
+                .val
+                .compare_exchange(0, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    /// Release the lock acquired by `lock_raw` or `try_lock_raw`.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held by the caller.
+    #[inline(always)]
+    pub unsafe fn unlock_raw(&self) {
+        self.val.fetch_sub(LOCKED, Ordering::Release);
+    }
+
+    /// Return `true` if the lock is held by someone.
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.val.load(Ordering::Relaxed) & LOCKED_MASK != 0
+    }
+
+    /// Return a pointer to the protected data.
+    #[inline(always)]
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    #[cold]
+    fn lock_slow(&self, mut val: u32) {
+        // the pending bit is being handed over to the locked byte
+        let mut loops = PENDING_LOOPS;
+        while val == PENDING && loops > 0 {
+            hint::spin_loop();
+            val = self.val.load(Ordering::Relaxed);
+            loops -= 1;
+        }
+
+        // join the queue if there are other waiters
+        if val & !LOCKED_MASK != 0 {
+            self.lock_queued();
+            return;
+        }
+
+        val = self.val.fetch_or(PENDING, Ordering::Acquire);
+        if val & !LOCKED_MASK != 0 {
+            // someone else became the pending waiter or joined the queue
+            if val & PENDING_MASK == 0 {
+                self.val.fetch_and(!PENDING, Ordering::Relaxed);
+            }
+
+            self.lock_queued();
+            return;
+        }
+
+        // I am the pending waiter, so wait for the holder to release the lock
+        if val & LOCKED_MASK != 0 {
+            while self.val.load(Ordering::Acquire) & LOCKED_MASK != 0 {
+                hint::spin_loop();
+            }
+        }
+
+        // clear the pending bit and take the lock
+        self.val.fetch_sub(PENDING - LOCKED, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    fn lock_queued(&self) {
+        let cpu = crate::cpu_id();
+        let per_cpu = &NODES[cpu];
+
+        let index = per_cpu.count.load(Ordering::Relaxed);
+        if index >= NUM_NODES {
+            // no node is left, so spin on the lock word like a spinlock
+            while !self.try_acquire() {
+                hint::spin_loop();
+            }
+            return;
+        }
+
+        per_cpu.count.store(index + 1, Ordering::Relaxed);
+        self.wait_in_queue(&per_cpu.nodes[index], encode_tail(cpu, index));
+        per_cpu.count.store(index, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    fn wait_in_queue(&self, node: &QNode, tail: u32) {
+        node.next.store(null_mut(), Ordering::Relaxed);
+        node.locked.store(false, Ordering::Relaxed);
+
+        // the lock may have been released while preparing the node
+        if self.try_acquire() {
+            return;
+        }
+
+        // set my node as the tail, and link it to the previous tail
+        let old = self.exchange_tail(tail);
+        if old & TAIL_MASK != 0 {
+            let prev = decode_tail(old);
+            prev.next
+                .store(node as *const QNode as *mut QNode, Ordering::Release);
+
+            // spin until the previous node passes the head of the queue
+            crate::mwait::wait_while_false(&node.locked);
+            fence(Ordering::Acquire);
+        }
+
+        // I am the head of the queue, so wait for the holder and the pending waiter
+        let mut val = self.val.load(Ordering::Acquire);
+        while val & (LOCKED_MASK | PENDING_MASK) != 0 {
+            hint::spin_loop();
+            val = self.val.load(Ordering::Acquire);
+        }
+
+        // clear the tail if I am the last waiter
+        if val & TAIL_MASK == tail
+            && self
+                .val
+                .compare_exchange(val, LOCKED, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            return;
+        }
+
+        // only the head of the queue sets the locked byte while the tail is set
+        self.val.fetch_or(LOCKED, Ordering::Relaxed);
+
+        crate::mwait::wait_while_null(&node.next);
+        let next = unsafe { &*node.next.load(Ordering::Acquire) };
+        next.locked.store(true, Ordering::Release);
+    }
+
+    /// Replace the tail, and return the previous word.
+    #[inline(always)]
+    fn exchange_tail(&self, tail: u32) -> u32 {
+        let mut val = self.val.load(Ordering::Relaxed);
+        loop {
+            match self.val.compare_exchange_weak(
+                val,
+                (val & !TAIL_MASK) | tail,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(old) => return old,
+                Err(current) => val = current,
+            }
+        }
+    }
+}
+
+unsafe impl RawLock for QSpinLock<()> {
+    const INIT: Self = QSpinLock::new(());
+
+    const USES_NODE: bool = false;
+
+    #[inline(always)]
+    unsafe fn lock(&self, _node: &mut MCSNode) {
+        self.lock_raw();
+    }
+
+    #[inline(always)]
+    unsafe fn try_lock(&self, _node: &mut MCSNode) -> bool {
+        self.try_lock_raw()
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self, _node: &MCSNode) {
+        self.unlock_raw();
+    }
+}
+
+unsafe impl RawLockSend for QSpinLock<()> {}
+
+pub struct QSpinLockGuard<'a, T> {
+    qspin_lock: &'a QSpinLock<T>,
+    access: WriteAccess<T>,
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<'a, T> QSpinLockGuard<'a, T> {
+    #[inline(always)]
+    fn new(
+        qspin_lock: &'a QSpinLock<T>,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        QSpinLockGuard {
+            qspin_lock,
+            access: WriteAccess::start(&qspin_lock.data),
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for QSpinLockGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.access.end();
+        unsafe { self.qspin_lock.unlock_raw() };
+    }
+}
+
+impl<T: Send> Deref for QSpinLockGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.access.get(&self.qspin_lock.data) }
+    }
+}
+
+impl<T: Send> DerefMut for QSpinLockGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.access.get(&self.qspin_lock.data) }
+    }
+}
+
+impl<T: Send> AsMut<T> for QSpinLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.access.get(&self.qspin_lock.data) }
+    }
+}
+
+impl<T: Send> AsRef<T> for QSpinLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &T {
+        unsafe { &*self.access.get(&self.qspin_lock.data) }
+    }
+}
@@ -1,142 +1,496 @@
+//! # Reader-writer lock
+//!
+//! `RwLock` allows multiple readers or a single writer at a time.
+//! How readers and writers are ordered is decided by a policy given as the second type parameter.
+//!
+//! - `WriterPreferring` (default without `std`): new readers wait while a writer is waiting.
+//! - `ReaderPreferring`: readers never wait for a waiting writer.
+//! - `PhaseFairTicket` (PF-T): readers and writers alternate, and writers are served in FIFO order by tickets.
+//! - `PhaseFairQueue` (PF-Q): the same as PF-T, but writers wait in an MCS queue.
+//!
+//! With the phase-fair policies, a reader waits for at most one writer,
+//! and a writer waits for at most one reader phase and the writers queued before it.
+//!
+//! With `std`, the default policy `DefaultPolicy` is `parking_lot::RawRwLock`,
+//! whose waiters sleep instead of spinning on the host.
+//!
+//! At most `MAX_READERS` readers hold the lock at the same time.
+//! What happens to a reader beyond that is decided by `ReaderOverflow`.
+
 use core::{
     marker::PhantomData,
+    mem::ManuallyDrop,
     ops::{Deref, DerefMut},
 };
 
 #[cfg(not(loom))]
-use core::{
-    cell::UnsafeCell,
-    sync::atomic::{AtomicUsize, Ordering},
-};
+use alloc::sync::Arc;
+
+#[cfg(not(loom))]
+use core::cell::UnsafeCell;
 
 #[cfg(loom)]
-use loom::{
-    cell::UnsafeCell,
-    sync::atomic::{AtomicUsize, Ordering},
-};
+use loom::cell::UnsafeCell;
+
+use crate::access::{ReadAccess, WriteAccess};
+
+mod phase_fair;
+mod reader_preferring;
+mod writer_preferring;
+
+#[cfg(not(loom))]
+pub use phase_fair::PhaseFairQueue;
+pub use phase_fair::PhaseFairTicket;
+pub use reader_preferring::ReaderPreferring;
+pub use writer_preferring::WriterPreferring;
+
+#[cfg(all(feature = "std", not(loom)))]
+mod std_backend;
+
+/// The policy of `RwLock` selected by the features.
+#[cfg(any(not(feature = "std"), loom))]
+pub type DefaultPolicy = WriterPreferring;
+
+/// The policy of `RwLock` selected by the features.
+#[cfg(all(feature = "std", not(loom)))]
+pub type DefaultPolicy = parking_lot::RawRwLock;
+
+/// The maximum number of readers holding `RwLock` at the same time.
+///
+/// The reader counts of the policies of this crate have room for more readers than this,
+/// so readers racing at the limit do not overflow them.
+/// `parking_lot::RawRwLock` is not limited by this.
+#[cfg(not(loom))]
+pub const MAX_READERS: usize = usize::MAX >> 3;
+
+/// Narrowed so that model tests can reach the limit.
+#[cfg(loom)]
+pub const MAX_READERS: usize = 2;
+
+/// What a reader does when `MAX_READERS` readers already hold `RwLock`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReaderOverflow {
+    /// Panic, because so many readers are usually leaked guards.
+    /// This is the default.
+    Panic,
+
+    /// Wait until some readers leave.
+    /// `RwLock::try_read` returns `None`.
+    Wait,
+
+    /// `RwLock::try_read`, `read_until` and `read_timeout` return `None`.
+    /// `RwLock::read` and `read_arc` panic, because they cannot fail.
+    Fail,
+}
+
+/// The raw part of `RwLock`, which decides the policy.
+///
+/// # Safety
+///
+/// A writer must exclude all other readers and writers,
+/// and the lock must be usable from multiple CPUs at the same time.
+pub unsafe trait RawRwLock {
+    /// The unlocked state.
+    #[cfg(not(loom))]
+    const INIT: Self;
+
+    /// Make the unlocked state.
+    #[cfg(loom)]
+    fn new() -> Self;
+
+    /// Acquire a reader lock.
+    /// This waits while `MAX_READERS` readers hold the lock.
+    fn lock_shared(&self);
+
+    /// Try to acquire a reader lock without waiting.
+    /// This fails while `MAX_READERS` readers hold the lock.
+    fn try_lock_shared(&self) -> bool;
+
+    /// Return `true` if `MAX_READERS` readers hold the lock.
+    fn is_full_of_readers(&self) -> bool;
+
+    /// Acquire a reader lock, but give up when `cancel` returns `true`.
+    fn lock_shared_until<F>(&self, cancel: F) -> bool
+    where
+        F: FnMut() -> bool;
+
+    /// Release a reader lock.
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold a reader lock.
+    unsafe fn unlock_shared(&self);
+
+    /// Acquire the writer lock.
+    fn lock_exclusive(&self);
+
+    /// Try to acquire the writer lock without waiting.
+    fn try_lock_exclusive(&self) -> bool;
+
+    /// Acquire the writer lock, but give up when `cancel` returns `true`.
+    fn lock_exclusive_until<F>(&self, cancel: F) -> bool
+    where
+        F: FnMut() -> bool;
+
+    /// Release the writer lock.
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold the writer lock.
+    unsafe fn unlock_exclusive(&self);
+
+    /// Convert the writer lock to a reader lock without letting other writers in.
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold the writer lock.
+    unsafe fn downgrade(&self);
+}
+
+/// A policy supporting upgradable reader locks.
+///
+/// # Safety
+///
+/// An upgradable reader must coexist only with plain readers.
+pub unsafe trait RawRwLockUpgrade: RawRwLock {
+    /// Acquire an upgradable reader lock.
+    fn lock_upgradable(&self);
+
+    /// Try to acquire an upgradable reader lock without waiting.
+    fn try_lock_upgradable(&self) -> bool;
+
+    /// Release an upgradable reader lock.
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold the upgradable reader lock.
+    unsafe fn unlock_upgradable(&self);
+
+    /// Convert the upgradable reader lock to the writer lock,
+    /// waiting for the other readers to leave.
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold the upgradable reader lock.
+    unsafe fn upgrade(&self);
 
-pub struct RwLock<T: Send> {
-    state: AtomicUsize,
-    writer_wake_counter: AtomicUsize,
+    /// Convert the upgradable reader lock to the writer lock if there are no other readers.
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold the upgradable reader lock.
+    unsafe fn try_upgrade(&self) -> bool;
+}
+
+/// A policy whose locks can be released on a CPU other than the one that acquired them.
+///
+/// `RwLock::read_arc` and `RwLock::write_arc` require this because their guards can be sent to another CPU.
+///
+/// # Safety
+///
+/// `unlock_shared` and `unlock_exclusive` must work on any CPU,
+/// and the lock must not keep per-CPU state that is accessed only by its CPU while the lock is held.
+pub unsafe trait RawRwLockSend: RawRwLock {}
+
+pub struct RwLock<T: Send, P: RawRwLock = DefaultPolicy> {
+    raw: P,
+    overflow: ReaderOverflow,
     data: UnsafeCell<T>,
 }
 
 impl<T: Send> RwLock<T> {
     #[cfg(not(loom))]
     pub const fn new(v: T) -> RwLock<T> {
+        RwLock::with_policy(v)
+    }
+
+    #[cfg(loom)]
+    pub fn new(v: T) -> RwLock<T> {
+        RwLock::with_policy(v)
+    }
+}
+
+impl<T: Send, P: RawRwLock> RwLock<T, P> {
+    /// Make a lock with the policy `P`.
+    ///
+    /// ```
+    /// use awkernel_sync::rwlock::{PhaseFairTicket, RwLock};
+    ///
+    /// let lock = RwLock::<_, PhaseFairTicket>::with_policy(0);
+    /// *lock.write() += 1;
+    /// assert_eq!(*lock.read(), 1);
+    /// ```
+    #[cfg(not(loom))]
+    pub const fn with_policy(v: T) -> Self {
         RwLock {
-            state: AtomicUsize::new(0),
-            writer_wake_counter: AtomicUsize::new(0),
+            raw: P::INIT,
+            overflow: ReaderOverflow::Panic,
             data: UnsafeCell::new(v),
         }
     }
 
+    /// Make a lock with the policy `P`.
     #[cfg(loom)]
-    pub fn new(v: T) -> RwLock<T> {
+    pub fn with_policy(v: T) -> Self {
         RwLock {
-            state: AtomicUsize::new(0),
-            writer_wake_counter: AtomicUsize::new(0),
+            raw: P::new(),
+            overflow: ReaderOverflow::Panic,
             data: UnsafeCell::new(v),
         }
     }
 
+    /// Set what a reader does when `MAX_READERS` readers already hold the lock.
+    ///
+    /// ```
+    /// use awkernel_sync::rwlock::{ReaderOverflow, RwLock};
+    ///
+    /// let lock = RwLock::new(0).with_reader_overflow(ReaderOverflow::Wait);
+    /// assert_eq!(*lock.read(), 0);
+    /// ```
+    pub const fn with_reader_overflow(mut self, overflow: ReaderOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Panic if no more readers are allowed and the policy does not wait.
+    #[inline(always)]
+    fn check_reader_overflow(&self) {
+        if self.overflow != ReaderOverflow::Wait && self.raw.is_full_of_readers() {
+            panic!("RwLock is held by MAX_READERS readers");
+        }
+    }
+
     /// acquire reader lock
     #[inline(always)]
-    pub fn read(&self) -> RwLockReadGuard<T> {
+    pub fn read(&self) -> RwLockReadGuard<'_, T, P> {
         let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.check_reader_overflow();
+        self.raw.lock_shared();
+        RwLockReadGuard::new(self, _interrupt_guard)
+    }
 
-        let mut s = self.state.load(Ordering::Relaxed);
-        loop {
-            if s & 1 == 0 {
-                match self.state.compare_exchange_weak(
-                    s,
-                    s + 2,
-                    Ordering::Acquire,
-                    Ordering::Relaxed,
-                ) {
-                    Ok(_) => {
-                        return RwLockReadGuard {
-                            rwlock: self,
-                            _interrupt_guard,
-                            _phantom: Default::default(),
-                        };
-                    }
-                    Err(e) => s = e,
-                }
-            }
+    /// acquire writer lock
+    #[inline(always)]
+    pub fn write(&self) -> RwLockWriteGuard<'_, T, P> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.raw.lock_exclusive();
+        RwLockWriteGuard::new(self, _interrupt_guard)
+    }
+
+    /// Call `f` with the data while holding the reader lock.
+    ///
+    /// ```
+    /// use awkernel_sync::rwlock::RwLock;
+    ///
+    /// let lock = RwLock::new(vec![1, 2]);
+    /// assert_eq!(lock.with_read(|data| data.len()), 2);
+    /// ```
+    #[inline(always)]
+    pub fn with_read<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        f(&self.read())
+    }
+
+    /// Call `f` with the data while holding the writer lock.
+    ///
+    /// ```
+    /// use awkernel_sync::rwlock::RwLock;
+    ///
+    /// let lock = RwLock::new(vec![1, 2]);
+    /// lock.with_write(|data| data.push(3));
+    /// assert_eq!(*lock.read(), [1, 2, 3]);
+    /// ```
+    #[inline(always)]
+    pub fn with_write<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        f(&mut self.write())
+    }
 
-            if s & 1 == 1 {
-                super::mwait::wait_while_equal(&self.state, s, Ordering::Relaxed);
-                s = self.state.load(Ordering::Relaxed);
+    /// Try to acquire the reader lock.
+    ///
+    /// Return `None` if the lock is held or waited for by a writer,
+    /// or if it is held by `MAX_READERS` readers and the overflow policy is not `ReaderOverflow::Panic`.
+    #[inline(always)]
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T, P>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        if self.raw.try_lock_shared() {
+            Some(RwLockReadGuard::new(self, _interrupt_guard))
+        } else {
+            if self.overflow == ReaderOverflow::Panic && self.raw.is_full_of_readers() {
+                panic!("RwLock is held by MAX_READERS readers");
             }
+            None
+        }
+    }
 
-            #[cfg(loom)]
-            loom::thread::yield_now();
+    /// Try to acquire the writer lock.
+    ///
+    /// Return `None` if the lock is held by readers or a writer.
+    #[inline(always)]
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T, P>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        if self.raw.try_lock_exclusive() {
+            Some(RwLockWriteGuard::new(self, _interrupt_guard))
+        } else {
+            None
         }
     }
 
-    /// acquire writer lock
+    /// Acquire the reader lock, but give up when the uptime reaches `deadline`.
+    ///
+    /// `deadline` is in microseconds and compared with the clock registered by `crate::set_uptime_fn`.
     #[inline(always)]
-    pub fn write(&self) -> RwLockWriteGuard<T> {
+    pub fn read_timeout(&self, deadline: u64) -> Option<RwLockReadGuard<'_, T, P>> {
+        self.read_until(|| crate::uptime() >= deadline)
+    }
+
+    /// Acquire the writer lock, but give up when the uptime reaches `deadline`.
+    ///
+    /// `deadline` is in microseconds and compared with the clock registered by `crate::set_uptime_fn`.
+    #[inline(always)]
+    pub fn write_timeout(&self, deadline: u64) -> Option<RwLockWriteGuard<'_, T, P>> {
+        self.write_until(|| crate::uptime() >= deadline)
+    }
+
+    /// Acquire the reader lock, but give up when `cancel` returns `true`.
+    ///
+    /// `cancel` is polled while waiting with interrupts disabled.
+    #[inline(always)]
+    pub fn read_until<F>(&self, cancel: F) -> Option<RwLockReadGuard<'_, T, P>>
+    where
+        F: FnMut() -> bool,
+    {
         let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
 
-        let mut s = self.state.load(Ordering::Relaxed);
-        loop {
-            if s <= 1 {
-                match self.state.compare_exchange(
-                    s,
-                    usize::MAX,
-                    Ordering::Acquire,
-                    Ordering::Relaxed,
-                ) {
-                    Ok(_) => {
-                        return RwLockWriteGuard {
-                            rwlock: self,
-                            _interrupt_guard,
-                            _phantom: Default::default(),
-                        };
-                    }
-                    Err(e) => {
-                        s = e;
-                        continue;
-                    }
-                }
-            }
+        match self.overflow {
+            ReaderOverflow::Panic => self.check_reader_overflow(),
+            ReaderOverflow::Fail if self.raw.is_full_of_readers() => return None,
+            _ => (),
+        }
 
-            if s & 1 == 0 {
-                match self
-                    .state
-                    .compare_exchange(s, s + 1, Ordering::Relaxed, Ordering::Relaxed)
-                {
-                    Ok(_) => (),
-                    Err(e) => {
-                        s = e;
-                        continue;
-                    }
-                }
-            }
+        if self.raw.lock_shared_until(cancel) {
+            Some(RwLockReadGuard::new(self, _interrupt_guard))
+        } else {
+            None
+        }
+    }
 
-            let w = self.writer_wake_counter.load(Ordering::Acquire);
-            s = self.state.load(Ordering::Relaxed);
+    /// Acquire the writer lock, but give up when `cancel` returns `true`.
+    ///
+    /// `cancel` is polled while waiting with interrupts disabled.
+    /// If it gives up, readers blocked by this writer are let in again.
+    #[inline(always)]
+    pub fn write_until<F>(&self, cancel: F) -> Option<RwLockWriteGuard<'_, T, P>>
+    where
+        F: FnMut() -> bool,
+    {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        if self.raw.lock_exclusive_until(cancel) {
+            Some(RwLockWriteGuard::new(self, _interrupt_guard))
+        } else {
+            None
+        }
+    }
 
-            if s >= 2 {
-                super::mwait::wait_while_equal(&self.writer_wake_counter, w, Ordering::Acquire);
-                s = self.state.load(Ordering::Relaxed);
-            }
+    /// Acquire the reader lock through an `Arc`.
+    ///
+    /// The returned guard owns a clone of the `Arc` and has no lifetime,
+    /// so it can be stored in a struct, held across `await` or sent to another thread.
+    ///
+    /// Interrupts are disabled only while waiting for the lock.
+    /// The guard does not keep them disabled because it may be dropped on another CPU,
+    /// so a rwlock locked by `read_arc` must not be locked by interrupt handlers.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub fn read_arc(self: &Arc<Self>) -> ArcRwLockReadGuard<T, P>
+    where
+        P: RawRwLockSend,
+    {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.check_reader_overflow();
+        self.raw.lock_shared();
+        ArcRwLockReadGuard {
+            rwlock: self.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Acquire the writer lock through an `Arc`.
+    ///
+    /// The returned guard owns a clone of the `Arc` and has no lifetime,
+    /// so it can be stored in a struct, held across `await` or sent to another thread.
+    ///
+    /// Interrupts are disabled only while waiting for the lock.
+    /// The guard does not keep them disabled because it may be dropped on another CPU,
+    /// so a rwlock locked by `write_arc` must not be locked by interrupt handlers.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub fn write_arc(self: &Arc<Self>) -> ArcRwLockWriteGuard<T, P>
+    where
+        P: RawRwLockSend,
+    {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.raw.lock_exclusive();
+        ArcRwLockWriteGuard {
+            rwlock: self.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
 
-            #[cfg(loom)]
-            loom::thread::yield_now();
+impl<T: Send, P: RawRwLockUpgrade> RwLock<T, P> {
+    /// Acquire an upgradable reader lock.
+    ///
+    /// An upgradable reader coexists with plain readers, but not with another upgradable reader.
+    /// It can be upgraded to the writer lock by `RwLockUpgradableGuard::upgrade`.
+    #[inline(always)]
+    pub fn upgradable_read(&self) -> RwLockUpgradableGuard<'_, T, P> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.raw.lock_upgradable();
+        RwLockUpgradableGuard::new(self, _interrupt_guard)
+    }
+
+    /// Try to acquire an upgradable reader lock.
+    ///
+    /// Return `None` if the lock is held by a writer or another upgradable reader,
+    /// or waited for by a writer.
+    #[inline(always)]
+    pub fn try_upgradable_read(&self) -> Option<RwLockUpgradableGuard<'_, T, P>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        if self.raw.try_lock_upgradable() {
+            Some(RwLockUpgradableGuard::new(self, _interrupt_guard))
+        } else {
+            None
         }
     }
 }
 
-pub struct RwLockReadGuard<'a, T: Send> {
-    rwlock: &'a RwLock<T>,
+pub struct RwLockReadGuard<'a, T: Send, P: RawRwLock = DefaultPolicy> {
+    rwlock: &'a RwLock<T, P>,
+    access: ReadAccess<T>,
     _interrupt_guard: crate::interrupt_guard::InterruptGuard,
     _phantom: PhantomData<*mut ()>,
 }
 
-impl<T: Send> RwLockReadGuard<'_, T> {
+impl<'a, T: Send, P: RawRwLock> RwLockReadGuard<'a, T, P> {
+    /// Make a guard of the reader lock held by the caller.
+    #[inline(always)]
+    fn new(
+        rwlock: &'a RwLock<T, P>,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        RwLockReadGuard {
+            rwlock,
+            access: ReadAccess::start(&rwlock.data),
+            _interrupt_guard,
+            _phantom: Default::default(),
+        }
+    }
+
     /// unlock read lock
     pub fn unlock(self) {}
 
@@ -145,50 +499,280 @@ impl<T: Send> RwLockReadGuard<'_, T> {
     where
         F: FnOnce(*const T) -> R,
     {
-        self.rwlock.data.with(f)
+        f(self.access.get(&self.rwlock.data))
+    }
+}
+
+impl<'a, T: Send, P: RawRwLock> RwLockReadGuard<'a, T, P> {
+    /// Make a guard for a part of the locked data.
+    ///
+    /// The read lock is held until the returned guard is dropped.
+    /// This is an associated function to avoid conflicts with methods of `T`.
+    #[inline(always)]
+    pub fn map<U, F>(guard: Self, f: F) -> MappedRwLockReadGuard<'a, T, U, P>
+    where
+        U: ?Sized,
+        F: FnOnce(&T) -> &U,
+    {
+        let data = f(&guard) as *const U;
+        MappedRwLockReadGuard {
+            _guard: guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<U, F>(guard: Self, f: F) -> Result<MappedRwLockReadGuard<'a, T, U, P>, Self>
+    where
+        U: ?Sized,
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        match f(&guard).map(|data| data as *const U) {
+            Some(data) => Ok(MappedRwLockReadGuard {
+                _guard: guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<U, E, F>(
+        guard: Self,
+        f: F,
+    ) -> Result<MappedRwLockReadGuard<'a, T, U, P>, (Self, E)>
+    where
+        U: ?Sized,
+        F: FnOnce(&T) -> Result<&U, E>,
+    {
+        match f(&guard).map(|data| data as *const U) {
+            Ok(data) => Ok(MappedRwLockReadGuard {
+                _guard: guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
+/// A guard made by `RwLock::upgradable_read`.
+///
+/// It gives read access, and can be upgraded to `RwLockWriteGuard`
+/// without releasing the lock.
+pub struct RwLockUpgradableGuard<'a, T: Send, P: RawRwLockUpgrade = DefaultPolicy> {
+    rwlock: &'a RwLock<T, P>,
+    access: ReadAccess<T>,
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<'a, T: Send, P: RawRwLockUpgrade> RwLockUpgradableGuard<'a, T, P> {
+    /// Make a guard of the upgradable reader lock held by the caller.
+    #[inline(always)]
+    fn new(
+        rwlock: &'a RwLock<T, P>,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        RwLockUpgradableGuard {
+            rwlock,
+            access: ReadAccess::start(&rwlock.data),
+            _interrupt_guard,
+            _phantom: Default::default(),
+        }
+    }
+
+    /// unlock upgradable read lock
+    pub fn unlock(self) {}
+
+    /// Upgrade to the writer lock, waiting for the other readers to leave.
+    ///
+    /// New readers are blocked while waiting.
+    /// This is an associated function to avoid conflicts with methods of `T`.
+    #[inline(always)]
+    pub fn upgrade(guard: Self) -> RwLockWriteGuard<'a, T, P> {
+        unsafe { guard.rwlock.raw.upgrade() };
+        let (rwlock, _interrupt_guard) = guard.into_parts();
+        RwLockWriteGuard::new(rwlock, _interrupt_guard)
+    }
+
+    /// Upgrade to the writer lock if there are no other readers.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn try_upgrade(guard: Self) -> Result<RwLockWriteGuard<'a, T, P>, Self> {
+        if unsafe { guard.rwlock.raw.try_upgrade() } {
+            let (rwlock, _interrupt_guard) = guard.into_parts();
+            Ok(RwLockWriteGuard::new(rwlock, _interrupt_guard))
+        } else {
+            Err(guard)
+        }
+    }
+
+    /// Take the fields out without releasing the lock.
+    #[inline(always)]
+    fn into_parts(self) -> (&'a RwLock<T, P>, crate::interrupt_guard::InterruptGuard) {
+        let mut guard = ManuallyDrop::new(self);
+        guard.access.end();
+        let interrupt_guard = unsafe { core::ptr::read(&guard._interrupt_guard) };
+        (guard.rwlock, interrupt_guard)
+    }
+
+    #[cfg(loom)]
+    pub fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(*const T) -> R,
+    {
+        f(self.access.get(&self.rwlock.data))
+    }
+}
+
+impl<T: Send, P: RawRwLockUpgrade> Deref for RwLockUpgradableGuard<'_, T, P> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.access.get(&self.rwlock.data) }
+    }
+}
+
+/// release upgradable read lock
+impl<T: Send, P: RawRwLockUpgrade> Drop for RwLockUpgradableGuard<'_, T, P> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.access.end();
+        unsafe { self.rwlock.raw.unlock_upgradable() };
     }
 }
 
-pub struct RwLockWriteGuard<'a, T: Send> {
-    rwlock: &'a RwLock<T>,
+pub struct RwLockWriteGuard<'a, T: Send, P: RawRwLock = DefaultPolicy> {
+    rwlock: &'a RwLock<T, P>,
+    access: WriteAccess<T>,
     _interrupt_guard: crate::interrupt_guard::InterruptGuard,
     _phantom: PhantomData<*mut ()>,
 }
 
-impl<T: Send> RwLockWriteGuard<'_, T> {
+impl<'a, T: Send, P: RawRwLock> RwLockWriteGuard<'a, T, P> {
+    /// Make a guard of the writer lock held by the caller.
+    #[inline(always)]
+    fn new(
+        rwlock: &'a RwLock<T, P>,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        RwLockWriteGuard {
+            rwlock,
+            access: WriteAccess::start(&rwlock.data),
+            _interrupt_guard,
+            _phantom: Default::default(),
+        }
+    }
+
     /// unlock write lock
     pub fn unlock(self) {}
 
+    /// Convert to the reader lock without releasing the lock.
+    ///
+    /// No writer can acquire the lock between them,
+    /// so the caller reads what it wrote.
+    /// This is an associated function to avoid conflicts with methods of `T`.
+    #[inline(always)]
+    pub fn downgrade(guard: Self) -> RwLockReadGuard<'a, T, P> {
+        let mut guard = ManuallyDrop::new(guard);
+        guard.access.end();
+        let _interrupt_guard = unsafe { core::ptr::read(&guard._interrupt_guard) };
+        unsafe { guard.rwlock.raw.downgrade() };
+        RwLockReadGuard::new(guard.rwlock, _interrupt_guard)
+    }
+
     #[cfg(loom)]
     pub fn with_mut<F, R>(&mut self, f: F) -> R
     where
         F: FnOnce(*mut T) -> R,
     {
-        self.rwlock.data.with_mut(f)
+        f(self.access.get(&self.rwlock.data))
     }
 }
 
-#[cfg(not(loom))]
-impl<T: Send> AsMut<T> for RwLockWriteGuard<'_, T> {
+impl<'a, T: Send, P: RawRwLock> RwLockWriteGuard<'a, T, P> {
+    /// Make a guard for a part of the locked data.
+    ///
+    /// The write lock is held until the returned guard is dropped.
+    /// This is an associated function to avoid conflicts with methods of `T`.
+    #[inline(always)]
+    pub fn map<U, F>(mut guard: Self, f: F) -> MappedRwLockWriteGuard<'a, T, U, P>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(&mut guard) as *mut U;
+        MappedRwLockWriteGuard {
+            _guard: guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<U, F>(
+        mut guard: Self,
+        f: F,
+    ) -> Result<MappedRwLockWriteGuard<'a, T, U, P>, Self>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(&mut guard).map(|data| data as *mut U) {
+            Some(data) => Ok(MappedRwLockWriteGuard {
+                _guard: guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<U, E, F>(
+        mut guard: Self,
+        f: F,
+    ) -> Result<MappedRwLockWriteGuard<'a, T, U, P>, (Self, E)>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> Result<&mut U, E>,
+    {
+        match f(&mut guard).map(|data| data as *mut U) {
+            Ok(data) => Ok(MappedRwLockWriteGuard {
+                _guard: guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
+impl<T: Send, P: RawRwLock> AsMut<T> for RwLockWriteGuard<'_, T, P> {
     #[inline(always)]
     fn as_mut(&mut self) -> &mut T {
-        unsafe { &mut *self.rwlock.data.get() }
+        unsafe { &mut *self.access.get(&self.rwlock.data) }
     }
 }
 
-#[cfg(not(loom))]
-impl<T: Send> AsRef<T> for RwLockWriteGuard<'_, T> {
+impl<T: Send, P: RawRwLock> AsRef<T> for RwLockWriteGuard<'_, T, P> {
     #[inline(always)]
     fn as_ref(&self) -> &T {
-        unsafe { &*self.rwlock.data.get() }
+        unsafe { &*self.access.get(&self.rwlock.data) }
     }
 }
 
-unsafe impl<T: Send> Sync for RwLock<T> {}
-unsafe impl<T: Send> Send for RwLock<T> {}
+unsafe impl<T: Send, P: RawRwLock> Sync for RwLock<T, P> {}
+unsafe impl<T: Send, P: RawRwLock> Send for RwLock<T, P> {}
 
 #[cfg(not(loom))]
-impl<T: Send> AsMut<T> for RwLockReadGuard<'_, T> {
+impl<T: Send, P: RawRwLock> AsMut<T> for RwLockReadGuard<'_, T, P> {
     #[inline(always)]
     fn as_mut(&mut self) -> &mut T {
         unsafe { &mut *self.rwlock.data.get() }
@@ -196,85 +780,287 @@ impl<T: Send> AsMut<T> for RwLockReadGuard<'_, T> {
 }
 
 #[cfg(not(loom))]
-impl<T: Send> AsRef<T> for RwLockReadGuard<'_, T> {
+impl<T: Send, P: RawRwLock> AsRef<T> for RwLockReadGuard<'_, T, P> {
     #[inline(always)]
     fn as_ref(&self) -> &T {
         unsafe { &*self.rwlock.data.get() }
     }
 }
 
-#[cfg(not(loom))]
-impl<T: Send> Deref for RwLockReadGuard<'_, T> {
+impl<T: Send, P: RawRwLock> Deref for RwLockReadGuard<'_, T, P> {
     type Target = T;
 
     #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        unsafe { &*self.rwlock.data.get() }
+        unsafe { &*self.access.get(&self.rwlock.data) }
     }
 }
 
-#[cfg(not(loom))]
-impl<T: Send> Deref for RwLockWriteGuard<'_, T> {
+impl<T: Send, P: RawRwLock> Deref for RwLockWriteGuard<'_, T, P> {
     type Target = T;
 
     #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        unsafe { &*self.rwlock.data.get() }
+        unsafe { &*self.access.get(&self.rwlock.data) }
     }
 }
 
-#[cfg(not(loom))]
-impl<T: Send> DerefMut for RwLockWriteGuard<'_, T> {
+impl<T: Send, P: RawRwLock> DerefMut for RwLockWriteGuard<'_, T, P> {
     #[inline(always)]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.rwlock.data.get() }
+        unsafe { &mut *self.access.get(&self.rwlock.data) }
     }
 }
 
 /// release read lock
-impl<T: Send> Drop for RwLockReadGuard<'_, T> {
+impl<T: Send, P: RawRwLock> Drop for RwLockReadGuard<'_, T, P> {
     #[inline(always)]
     fn drop(&mut self) {
-        if self.rwlock.state.fetch_sub(2, Ordering::Release) == 3 {
-            self.rwlock
-                .writer_wake_counter
-                .fetch_add(1, Ordering::Release);
-        }
+        self.access.end();
+        unsafe { self.rwlock.raw.unlock_shared() };
     }
 }
 
 /// release write lock
-impl<T: Send> Drop for RwLockWriteGuard<'_, T> {
+impl<T: Send, P: RawRwLock> Drop for RwLockWriteGuard<'_, T, P> {
     #[inline(always)]
     fn drop(&mut self) {
-        self.rwlock.state.store(0, Ordering::Release);
-        self.rwlock
-            .writer_wake_counter
-            .fetch_add(1, Ordering::Release);
+        self.access.end();
+        unsafe { self.rwlock.raw.unlock_exclusive() };
     }
 }
 
-#[cfg(loom)]
-impl<'a, T: Send> Deref for RwLockReadGuard<'a, T> {
+/// A guard for a part of the data protected by `RwLock`,
+/// made by `RwLockReadGuard::map`, `filter_map` or `try_map`.
+///
+/// The read lock and the interrupt guard are held by the original guard inside.
+pub struct MappedRwLockReadGuard<'a, T: Send, U: ?Sized, P: RawRwLock = DefaultPolicy> {
+    _guard: RwLockReadGuard<'a, T, P>,
+    data: *const U,
+}
+
+impl<'a, T: Send, U: ?Sized, P: RawRwLock> MappedRwLockReadGuard<'a, T, U, P> {
+    /// Make a guard for a part of the mapped data.
+    #[inline(always)]
+    pub fn map<V, F>(guard: Self, f: F) -> MappedRwLockReadGuard<'a, T, V, P>
+    where
+        V: ?Sized,
+        F: FnOnce(&U) -> &V,
+    {
+        let data = f(unsafe { &*guard.data }) as *const V;
+        MappedRwLockReadGuard {
+            _guard: guard._guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<V, F>(guard: Self, f: F) -> Result<MappedRwLockReadGuard<'a, T, V, P>, Self>
+    where
+        V: ?Sized,
+        F: FnOnce(&U) -> Option<&V>,
+    {
+        match f(unsafe { &*guard.data }).map(|data| data as *const V) {
+            Some(data) => Ok(MappedRwLockReadGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<V, E, F>(
+        guard: Self,
+        f: F,
+    ) -> Result<MappedRwLockReadGuard<'a, T, V, P>, (Self, E)>
+    where
+        V: ?Sized,
+        F: FnOnce(&U) -> Result<&V, E>,
+    {
+        match f(unsafe { &*guard.data }).map(|data| data as *const V) {
+            Ok(data) => Ok(MappedRwLockReadGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
+impl<T: Send, U: ?Sized, P: RawRwLock> Deref for MappedRwLockReadGuard<'_, T, U, P> {
+    type Target = U;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+/// A guard for a part of the data protected by `RwLock`,
+/// made by `RwLockWriteGuard::map`, `filter_map` or `try_map`.
+///
+/// The write lock and the interrupt guard are held by the original guard inside.
+pub struct MappedRwLockWriteGuard<'a, T: Send, U: ?Sized, P: RawRwLock = DefaultPolicy> {
+    _guard: RwLockWriteGuard<'a, T, P>,
+    data: *mut U,
+}
+
+impl<'a, T: Send, U: ?Sized, P: RawRwLock> MappedRwLockWriteGuard<'a, T, U, P> {
+    /// Make a guard for a part of the mapped data.
+    #[inline(always)]
+    pub fn map<V, F>(guard: Self, f: F) -> MappedRwLockWriteGuard<'a, T, V, P>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> &mut V,
+    {
+        let data = f(unsafe { &mut *guard.data }) as *mut V;
+        MappedRwLockWriteGuard {
+            _guard: guard._guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<V, F>(guard: Self, f: F) -> Result<MappedRwLockWriteGuard<'a, T, V, P>, Self>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> Option<&mut V>,
+    {
+        match f(unsafe { &mut *guard.data }).map(|data| data as *mut V) {
+            Some(data) => Ok(MappedRwLockWriteGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<V, E, F>(
+        guard: Self,
+        f: F,
+    ) -> Result<MappedRwLockWriteGuard<'a, T, V, P>, (Self, E)>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> Result<&mut V, E>,
+    {
+        match f(unsafe { &mut *guard.data }).map(|data| data as *mut V) {
+            Ok(data) => Ok(MappedRwLockWriteGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
+impl<T: Send, U: ?Sized, P: RawRwLock> Deref for MappedRwLockWriteGuard<'_, T, U, P> {
+    type Target = U;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<T: Send, U: ?Sized, P: RawRwLock> DerefMut for MappedRwLockWriteGuard<'_, T, U, P> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data }
+    }
+}
+
+/// A reader guard made by `RwLock::read_arc`, which keeps the `RwLock` alive by an `Arc`.
+///
+/// Unlike `RwLockReadGuard`, this is `Send` and does not keep interrupts disabled.
+#[cfg(not(loom))]
+pub struct ArcRwLockReadGuard<T: Send, P: RawRwLock = DefaultPolicy> {
+    rwlock: Arc<RwLock<T, P>>,
+    _phantom: PhantomData<*mut ()>,
+}
+
+#[cfg(not(loom))]
+unsafe impl<T: Send + Sync, P: RawRwLock> Send for ArcRwLockReadGuard<T, P> {}
+
+#[cfg(not(loom))]
+impl<T: Send, P: RawRwLock> ArcRwLockReadGuard<T, P> {
+    /// Return the `Arc` of the locked `RwLock`.
+    #[inline(always)]
+    pub fn rwlock(guard: &Self) -> &Arc<RwLock<T, P>> {
+        &guard.rwlock
+    }
+}
+
+#[cfg(not(loom))]
+impl<T: Send, P: RawRwLock> Deref for ArcRwLockReadGuard<T, P> {
     type Target = T;
 
+    #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        unimplemented!("loom does not support deref");
+        unsafe { &*self.rwlock.data.get() }
     }
 }
 
-#[cfg(loom)]
-impl<'a, T: Send> Deref for RwLockWriteGuard<'a, T> {
+#[cfg(not(loom))]
+impl<T: Send, P: RawRwLock> Drop for ArcRwLockReadGuard<T, P> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        unsafe { self.rwlock.raw.unlock_shared() };
+    }
+}
+
+/// A writer guard made by `RwLock::write_arc`, which keeps the `RwLock` alive by an `Arc`.
+///
+/// Unlike `RwLockWriteGuard`, this is `Send` and does not keep interrupts disabled.
+#[cfg(not(loom))]
+pub struct ArcRwLockWriteGuard<T: Send, P: RawRwLock = DefaultPolicy> {
+    rwlock: Arc<RwLock<T, P>>,
+    _phantom: PhantomData<*mut ()>,
+}
+
+#[cfg(not(loom))]
+unsafe impl<T: Send, P: RawRwLock> Send for ArcRwLockWriteGuard<T, P> {}
+
+#[cfg(not(loom))]
+impl<T: Send, P: RawRwLock> ArcRwLockWriteGuard<T, P> {
+    /// Return the `Arc` of the locked `RwLock`.
+    #[inline(always)]
+    pub fn rwlock(guard: &Self) -> &Arc<RwLock<T, P>> {
+        &guard.rwlock
+    }
+}
+
+#[cfg(not(loom))]
+impl<T: Send, P: RawRwLock> Deref for ArcRwLockWriteGuard<T, P> {
     type Target = T;
 
+    #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        unimplemented!("loom does not support deref");
+        unsafe { &*self.rwlock.data.get() }
     }
 }
 
-#[cfg(loom)]
-impl<'a, T: Send> DerefMut for RwLockWriteGuard<'a, T> {
+#[cfg(not(loom))]
+impl<T: Send, P: RawRwLock> DerefMut for ArcRwLockWriteGuard<T, P> {
+    #[inline(always)]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unimplemented!("loom does not support deref_mut");
+        unsafe { &mut *self.rwlock.data.get() }
+    }
+}
+
+#[cfg(not(loom))]
+impl<T: Send, P: RawRwLock> Drop for ArcRwLockWriteGuard<T, P> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        unsafe { self.rwlock.raw.unlock_exclusive() };
     }
 }
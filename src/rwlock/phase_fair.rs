@@ -0,0 +1,489 @@
+//! Phase-fair reader-writer locks by Brandenburg and Anderson.
+//!
+//! Reader and writer phases alternate.
+//! Readers arriving during a writer phase enter together when the writer leaves,
+//! even if another writer is waiting,
+//! and a writer waits only for the readers that entered before it.
+
+#[cfg(not(loom))]
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(not(loom))]
+use crate::mcs::{node_pool::PooledNode, MCSLock};
+
+use super::{RawRwLock, RawRwLockSend, MAX_READERS};
+
+/// The phase ID of the present writer, which alternates between successive writers.
+const PHASE_ID: usize = 1;
+
+/// A writer is present.
+const PRESENT: usize = 2;
+
+const WRITER_BITS: usize = PRESENT | PHASE_ID;
+
+/// The unit of the reader counts.
+const READER: usize = 4;
+
+/// The reader side shared by PF-T and PF-Q.
+///
+/// `rin` counts entering readers and holds the bits of the present writer,
+/// and `rout` counts leaving readers.
+/// Only the writer whose turn has come accesses `phase`.
+struct Phases {
+    rin: AtomicUsize,
+    rout: AtomicUsize,
+    phase: AtomicUsize,
+}
+
+impl Phases {
+    #[cfg(not(loom))]
+    const fn new() -> Self {
+        Self {
+            rin: AtomicUsize::new(0),
+            rout: AtomicUsize::new(0),
+            phase: AtomicUsize::new(0),
+        }
+    }
+
+    #[cfg(loom)]
+    fn new() -> Self {
+        Self {
+            rin: AtomicUsize::new(0),
+            rout: AtomicUsize::new(0),
+            phase: AtomicUsize::new(0),
+        }
+    }
+
+    /// Return `true` if `MAX_READERS` readers hold the lock or wait for the present writer.
+    ///
+    /// `o` must be loaded from `rout` before `r` is loaded from `rin`,
+    /// so that `o` does not count readers not counted in `r`.
+    #[inline(always)]
+    fn full(r: usize, o: usize) -> bool {
+        (r & !WRITER_BITS).wrapping_sub(o) / READER >= MAX_READERS
+    }
+
+    #[inline(always)]
+    fn is_full_of_readers(&self) -> bool {
+        let o = self.rout.load(Ordering::Acquire);
+        Self::full(self.rin.load(Ordering::Relaxed), o)
+    }
+
+    /// Wait while `MAX_READERS` readers hold the lock.
+    ///
+    /// Taking back `rin` would break writers waiting for `rout` to reach a value,
+    /// so readers are checked before counted,
+    /// and readers racing here can exceed `MAX_READERS` by the number of CPUs.
+    #[inline(always)]
+    fn wait_readers(&self) {
+        loop {
+            let o = self.rout.load(Ordering::Acquire);
+            if !Self::full(self.rin.load(Ordering::Relaxed), o) {
+                return;
+            }
+
+            crate::mwait::wait_while_equal(&self.rout, o, Ordering::Relaxed);
+        }
+    }
+
+    #[inline(always)]
+    fn lock_shared(&self) {
+        self.wait_readers();
+
+        let w = self.rin.fetch_add(READER, Ordering::Acquire) & WRITER_BITS;
+        if w == 0 {
+            return;
+        }
+
+        // wait only for the present writer,
+        // because the next writer has the other phase ID
+        let mut r = self.rin.load(Ordering::Acquire);
+        while r & WRITER_BITS == w {
+            crate::mwait::wait_while_equal(&self.rin, r, Ordering::Relaxed);
+            r = self.rin.load(Ordering::Acquire);
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock_shared(&self) -> bool {
+        let o = self.rout.load(Ordering::Acquire);
+        let mut r = self.rin.load(Ordering::Relaxed);
+        while r & WRITER_BITS == 0 && !Self::full(r, o) {
+            match self.rin.compare_exchange_weak(
+                r,
+                r.wrapping_add(READER),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(e) => r = e,
+            }
+        }
+
+        false
+    }
+
+    /// A reader counted in `rin` cannot leave before the writer leaves,
+    /// so this waits without being counted.
+    #[inline(always)]
+    fn lock_shared_until<F>(&self, mut cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        loop {
+            if self.try_lock_shared() {
+                return true;
+            }
+
+            let o = self.rout.load(Ordering::Acquire);
+            let r = self.rin.load(Ordering::Relaxed);
+            if r & WRITER_BITS != 0 {
+                if !crate::mwait::wait_while_equal_until(
+                    &self.rin,
+                    r,
+                    Ordering::Relaxed,
+                    &mut cancel,
+                ) {
+                    return false;
+                }
+            } else if Self::full(r, o)
+                && !crate::mwait::wait_while_equal_until(
+                    &self.rout,
+                    o,
+                    Ordering::Relaxed,
+                    &mut cancel,
+                )
+            {
+                return false;
+            }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    fn unlock_shared(&self) {
+        self.rout.fetch_add(READER, Ordering::Release);
+    }
+
+    /// Start a writer phase and wait for the readers that entered before it.
+    /// The caller must be the only writer.
+    #[inline(always)]
+    fn lock_exclusive(&self) {
+        let phase = self.phase.load(Ordering::Relaxed);
+        self.phase.store(phase.wrapping_add(1), Ordering::Relaxed);
+
+        let r = self
+            .rin
+            .fetch_add(PRESENT | (phase & PHASE_ID), Ordering::Relaxed);
+
+        let mut o = self.rout.load(Ordering::Acquire);
+        while o != r {
+            crate::mwait::wait_while_equal(&self.rout, o, Ordering::Acquire);
+            o = self.rout.load(Ordering::Acquire);
+        }
+    }
+
+    /// Start a writer phase if there are no readers.
+    /// The caller must be the only writer.
+    #[inline(always)]
+    fn try_lock_exclusive(&self) -> bool {
+        let r = self.rin.load(Ordering::Relaxed);
+        if self.rout.load(Ordering::Acquire) != r {
+            return false;
+        }
+
+        let phase = self.phase.load(Ordering::Relaxed);
+        if self
+            .rin
+            .compare_exchange(
+                r,
+                r | PRESENT | (phase & PHASE_ID),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return false;
+        }
+
+        self.phase.store(phase.wrapping_add(1), Ordering::Relaxed);
+        true
+    }
+
+    #[inline(always)]
+    fn unlock_exclusive(&self) {
+        self.rin.fetch_and(!WRITER_BITS, Ordering::Release);
+    }
+
+    #[inline(always)]
+    fn downgrade(&self) {
+        // clear the writer bits and count the caller as a reader at once
+        let w = self.rin.load(Ordering::Relaxed) & WRITER_BITS;
+        self.rin.fetch_add(READER - w, Ordering::Release);
+    }
+}
+
+/// The phase-fair ticket policy (PF-T).
+///
+/// Writers are served in FIFO order by tickets.
+/// `RwLock::write_until` and `RwLock::write_timeout` do not take a ticket,
+/// so they are not ordered with other writers.
+pub struct PhaseFairTicket {
+    phases: Phases,
+    win: AtomicUsize,
+    wout: AtomicUsize,
+}
+
+unsafe impl RawRwLock for PhaseFairTicket {
+    #[cfg(not(loom))]
+    const INIT: Self = Self {
+        phases: Phases::new(),
+        win: AtomicUsize::new(0),
+        wout: AtomicUsize::new(0),
+    };
+
+    #[cfg(loom)]
+    fn new() -> Self {
+        Self {
+            phases: Phases::new(),
+            win: AtomicUsize::new(0),
+            wout: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn lock_shared(&self) {
+        self.phases.lock_shared();
+    }
+
+    #[inline(always)]
+    fn try_lock_shared(&self) -> bool {
+        self.phases.try_lock_shared()
+    }
+
+    #[inline(always)]
+    fn lock_shared_until<F>(&self, cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        self.phases.lock_shared_until(cancel)
+    }
+
+    #[inline(always)]
+    fn is_full_of_readers(&self) -> bool {
+        self.phases.is_full_of_readers()
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_shared(&self) {
+        self.phases.unlock_shared();
+    }
+
+    #[inline(always)]
+    fn lock_exclusive(&self) {
+        let ticket = self.win.fetch_add(1, Ordering::Relaxed);
+
+        let mut t = self.wout.load(Ordering::Acquire);
+        while t != ticket {
+            crate::mwait::wait_while_equal(&self.wout, t, Ordering::Acquire);
+            t = self.wout.load(Ordering::Acquire);
+        }
+
+        self.phases.lock_exclusive();
+    }
+
+    #[inline(always)]
+    fn try_lock_exclusive(&self) -> bool {
+        // take a ticket only if no writer is holding or waiting
+        let t = self.wout.load(Ordering::Acquire);
+        if self
+            .win
+            .compare_exchange(t, t.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+
+        if self.phases.try_lock_exclusive() {
+            true
+        } else {
+            self.wout.fetch_add(1, Ordering::Release);
+            false
+        }
+    }
+
+    #[inline(always)]
+    fn lock_exclusive_until<F>(&self, mut cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        loop {
+            if self.try_lock_exclusive() {
+                return true;
+            }
+
+            if cancel() {
+                return false;
+            }
+
+            core::hint::spin_loop();
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_exclusive(&self) {
+        self.phases.unlock_exclusive();
+        self.wout.fetch_add(1, Ordering::Release);
+    }
+
+    #[inline(always)]
+    unsafe fn downgrade(&self) {
+        self.phases.downgrade();
+        self.wout.fetch_add(1, Ordering::Release);
+    }
+}
+
+unsafe impl RawRwLockSend for PhaseFairTicket {}
+
+/// The phase-fair queue policy (PF-Q).
+///
+/// Writers wait in an MCS queue with nodes from the per-CPU node pool,
+/// so they spin locally and are served in FIFO order.
+/// The node is kept until the writer lock is released,
+/// which may happen on another CPU with `RwLock::write_arc`.
+/// `RwLock::write_until` and `RwLock::write_timeout` do not wait in the queue,
+/// so they are not ordered with other writers.
+#[cfg(not(loom))]
+pub struct PhaseFairQueue {
+    phases: Phases,
+    writers: MCSLock<()>,
+
+    /// The node of the writer holding `writers`.
+    node: UnsafeCell<Option<PooledNode>>,
+}
+
+#[cfg(not(loom))]
+unsafe impl Sync for PhaseFairQueue {}
+
+#[cfg(not(loom))]
+impl PhaseFairQueue {
+    /// # Safety
+    ///
+    /// The caller must hold `writers`.
+    #[inline(always)]
+    unsafe fn unlock_writers(&self) {
+        let node = (*self.node.get()).take().unwrap();
+        self.writers.unlock_raw(&*node.get());
+    }
+}
+
+#[cfg(not(loom))]
+unsafe impl RawRwLock for PhaseFairQueue {
+    const INIT: Self = Self {
+        phases: Phases::new(),
+        writers: MCSLock::new(()),
+        node: UnsafeCell::new(None),
+    };
+
+    #[inline(always)]
+    fn lock_shared(&self) {
+        self.phases.lock_shared();
+    }
+
+    #[inline(always)]
+    fn try_lock_shared(&self) -> bool {
+        self.phases.try_lock_shared()
+    }
+
+    #[inline(always)]
+    fn lock_shared_until<F>(&self, cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        self.phases.lock_shared_until(cancel)
+    }
+
+    #[inline(always)]
+    fn is_full_of_readers(&self) -> bool {
+        self.phases.is_full_of_readers()
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_shared(&self) {
+        self.phases.unlock_shared();
+    }
+
+    #[inline(always)]
+    fn lock_exclusive(&self) {
+        let node = PooledNode::new();
+        unsafe {
+            self.writers.lock_raw(&mut *node.get());
+            *self.node.get() = Some(node);
+        }
+
+        self.phases.lock_exclusive();
+    }
+
+    #[inline(always)]
+    fn try_lock_exclusive(&self) -> bool {
+        let node = PooledNode::new();
+        unsafe {
+            if !self.writers.try_lock_raw(&mut *node.get()) {
+                return false;
+            }
+
+            if self.phases.try_lock_exclusive() {
+                *self.node.get() = Some(node);
+                true
+            } else {
+                self.writers.unlock_raw(&*node.get());
+                false
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn lock_exclusive_until<F>(&self, mut cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        loop {
+            if self.try_lock_exclusive() {
+                return true;
+            }
+
+            if cancel() {
+                return false;
+            }
+
+            core::hint::spin_loop();
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_exclusive(&self) {
+        self.phases.unlock_exclusive();
+        self.unlock_writers();
+    }
+
+    #[inline(always)]
+    unsafe fn downgrade(&self) {
+        self.phases.downgrade();
+        self.unlock_writers();
+    }
+}
+
+// the node is returned to the pool of the CPU that took it, from any CPU
+#[cfg(not(loom))]
+unsafe impl RawRwLockSend for PhaseFairQueue {}
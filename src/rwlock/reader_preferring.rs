@@ -0,0 +1,229 @@
+#[cfg(not(loom))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{RawRwLock, RawRwLockSend, MAX_READERS};
+
+/// A writer holds the lock.
+const WRITER: usize = 1;
+
+/// The unit of the reader count.
+const READER: usize = 2;
+
+/// The reader-preferring policy.
+///
+/// Readers enter whenever no writer holds the lock,
+/// so writers can starve while readers keep coming.
+pub struct ReaderPreferring {
+    state: AtomicUsize,
+}
+
+impl ReaderPreferring {
+    /// Wait until the writer leaves.
+    /// The caller has already been counted as a reader.
+    #[inline(always)]
+    fn wait_writer(&self) {
+        let mut s = self.state.load(Ordering::Acquire);
+        while s & WRITER != 0 {
+            crate::mwait::wait_while_equal(&self.state, s, Ordering::Relaxed);
+            s = self.state.load(Ordering::Acquire);
+        }
+    }
+
+    /// Count the caller as a reader unless `MAX_READERS` readers hold the lock.
+    /// Return the state before counting.
+    #[inline(always)]
+    fn count_reader(&self) -> Option<usize> {
+        let s = self.state.fetch_add(READER, Ordering::Acquire);
+        if s / READER < MAX_READERS {
+            Some(s)
+        } else {
+            // a writer cannot enter while this is counted, so just take it back
+            self.state.fetch_sub(READER, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+unsafe impl RawRwLock for ReaderPreferring {
+    #[cfg(not(loom))]
+    const INIT: Self = Self {
+        state: AtomicUsize::new(0),
+    };
+
+    #[cfg(loom)]
+    fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn lock_shared(&self) {
+        // a writer cannot enter after this, because it waits for the count to be 0
+        let s = loop {
+            if let Some(s) = self.count_reader() {
+                break s;
+            }
+
+            let mut s = self.state.load(Ordering::Relaxed);
+            while s / READER >= MAX_READERS {
+                crate::mwait::wait_while_equal(&self.state, s, Ordering::Relaxed);
+                s = self.state.load(Ordering::Relaxed);
+            }
+        };
+
+        if s & WRITER != 0 {
+            self.wait_writer();
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock_shared(&self) -> bool {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while s & WRITER == 0 && s / READER < MAX_READERS {
+            match self.state.compare_exchange_weak(
+                s,
+                s + READER,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(e) => s = e,
+            }
+        }
+
+        false
+    }
+
+    #[inline(always)]
+    fn lock_shared_until<F>(&self, mut cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        let s = loop {
+            if let Some(s) = self.count_reader() {
+                break s;
+            }
+
+            let mut s = self.state.load(Ordering::Relaxed);
+            while s / READER >= MAX_READERS {
+                if !crate::mwait::wait_while_equal_until(
+                    &self.state,
+                    s,
+                    Ordering::Relaxed,
+                    &mut cancel,
+                ) {
+                    return false;
+                }
+                s = self.state.load(Ordering::Relaxed);
+            }
+        };
+
+        if s & WRITER == 0 {
+            return true;
+        }
+
+        let mut s = self.state.load(Ordering::Acquire);
+        while s & WRITER != 0 {
+            if !crate::mwait::wait_while_equal_until(&self.state, s, Ordering::Relaxed, &mut cancel)
+            {
+                self.state.fetch_sub(READER, Ordering::Relaxed);
+                return false;
+            }
+            s = self.state.load(Ordering::Acquire);
+        }
+
+        true
+    }
+
+    #[inline(always)]
+    fn is_full_of_readers(&self) -> bool {
+        self.state.load(Ordering::Relaxed) / READER >= MAX_READERS
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_shared(&self) {
+        self.state.fetch_sub(READER, Ordering::Release);
+    }
+
+    #[inline(always)]
+    fn lock_exclusive(&self) {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if s == 0 {
+                match self.state.compare_exchange_weak(
+                    0,
+                    WRITER,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return,
+                    Err(e) => s = e,
+                }
+            } else {
+                crate::mwait::wait_while_equal(&self.state, s, Ordering::Relaxed);
+                s = self.state.load(Ordering::Relaxed);
+            }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock_exclusive(&self) -> bool {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    #[inline(always)]
+    fn lock_exclusive_until<F>(&self, mut cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if s == 0 {
+                match self.state.compare_exchange_weak(
+                    0,
+                    WRITER,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return true,
+                    Err(e) => s = e,
+                }
+            } else {
+                if !crate::mwait::wait_while_equal_until(
+                    &self.state,
+                    s,
+                    Ordering::Relaxed,
+                    &mut cancel,
+                ) {
+                    return false;
+                }
+                s = self.state.load(Ordering::Relaxed);
+            }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_exclusive(&self) {
+        self.state.fetch_sub(WRITER, Ordering::Release);
+    }
+
+    #[inline(always)]
+    unsafe fn downgrade(&self) {
+        // clear the writer bit and count the caller as a reader at once
+        self.state.fetch_add(READER - WRITER, Ordering::Release);
+    }
+}
+
+unsafe impl RawRwLockSend for ReaderPreferring {}
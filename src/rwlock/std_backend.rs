@@ -0,0 +1,127 @@
+//! `parking_lot::RawRwLock` as a policy, which is the default with `std`.
+//!
+//! Waiters sleep in the kernel of the host instead of spinning.
+//! parking_lot does not tell how many readers hold the lock,
+//! so `MAX_READERS` is not checked and parking_lot panics by itself if its count overflows.
+
+use core::time::Duration;
+
+use parking_lot::lock_api;
+
+use super::{RawRwLock, RawRwLockSend, RawRwLockUpgrade};
+
+/// How long `*_until` sleeps before calling `cancel` again.
+const CANCEL_INTERVAL: Duration = Duration::from_micros(100);
+
+unsafe impl RawRwLock for parking_lot::RawRwLock {
+    const INIT: Self = <Self as lock_api::RawRwLock>::INIT;
+
+    #[inline(always)]
+    fn lock_shared(&self) {
+        lock_api::RawRwLock::lock_shared(self);
+    }
+
+    #[inline(always)]
+    fn try_lock_shared(&self) -> bool {
+        lock_api::RawRwLock::try_lock_shared(self)
+    }
+
+    #[inline(always)]
+    fn is_full_of_readers(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn lock_shared_until<F>(&self, mut cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        loop {
+            if lock_api::RawRwLock::try_lock_shared(self) {
+                return true;
+            }
+
+            if cancel() {
+                return false;
+            }
+
+            if lock_api::RawRwLockTimed::try_lock_shared_for(self, CANCEL_INTERVAL) {
+                return true;
+            }
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_shared(&self) {
+        lock_api::RawRwLock::unlock_shared(self);
+    }
+
+    #[inline(always)]
+    fn lock_exclusive(&self) {
+        lock_api::RawRwLock::lock_exclusive(self);
+    }
+
+    #[inline(always)]
+    fn try_lock_exclusive(&self) -> bool {
+        lock_api::RawRwLock::try_lock_exclusive(self)
+    }
+
+    #[inline(always)]
+    fn lock_exclusive_until<F>(&self, mut cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        loop {
+            if lock_api::RawRwLock::try_lock_exclusive(self) {
+                return true;
+            }
+
+            if cancel() {
+                return false;
+            }
+
+            if lock_api::RawRwLockTimed::try_lock_exclusive_for(self, CANCEL_INTERVAL) {
+                return true;
+            }
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_exclusive(&self) {
+        lock_api::RawRwLock::unlock_exclusive(self);
+    }
+
+    #[inline(always)]
+    unsafe fn downgrade(&self) {
+        lock_api::RawRwLockDowngrade::downgrade(self);
+    }
+}
+
+unsafe impl RawRwLockSend for parking_lot::RawRwLock {}
+
+unsafe impl RawRwLockUpgrade for parking_lot::RawRwLock {
+    #[inline(always)]
+    fn lock_upgradable(&self) {
+        lock_api::RawRwLockUpgrade::lock_upgradable(self);
+    }
+
+    #[inline(always)]
+    fn try_lock_upgradable(&self) -> bool {
+        lock_api::RawRwLockUpgrade::try_lock_upgradable(self)
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_upgradable(&self) {
+        lock_api::RawRwLockUpgrade::unlock_upgradable(self);
+    }
+
+    #[inline(always)]
+    unsafe fn upgrade(&self) {
+        lock_api::RawRwLockUpgrade::upgrade(self);
+    }
+
+    #[inline(always)]
+    unsafe fn try_upgrade(&self) -> bool {
+        lock_api::RawRwLockUpgrade::try_upgrade(self)
+    }
+}
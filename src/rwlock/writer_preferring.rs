@@ -0,0 +1,423 @@
+#[cfg(not(loom))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{RawRwLock, RawRwLockSend, RawRwLockUpgrade, MAX_READERS};
+
+/// A writer is waiting for readers to leave, so new readers must wait.
+const WRITER_WAITING: usize = 1;
+
+/// An upgradable reader holds the lock.
+const UPGRADABLE: usize = 2;
+
+/// The unit of the reader count.
+const READER: usize = 4;
+
+/// A writer holds the lock.
+const WRITE_LOCKED: usize = usize::MAX;
+
+/// The writer-preferring policy, which is the default of `RwLock`.
+///
+/// Once a writer starts waiting, new readers wait until a writer acquires the lock.
+/// Writers are not ordered among themselves.
+pub struct WriterPreferring {
+    state: AtomicUsize,
+    writer_wake_counter: AtomicUsize,
+}
+
+/// Return `true` if a reader can enter at `s`.
+#[inline(always)]
+fn can_read(s: usize) -> bool {
+    // `WRITE_LOCKED` has the writer waiting bit
+    s & WRITER_WAITING == 0 && s / READER < MAX_READERS
+}
+
+impl WriterPreferring {
+    /// Clear the writer waiting bit set by a writer giving up.
+    ///
+    /// Other waiting writers are woken up to set the bit again,
+    /// because the last reader does not wake them up without the bit.
+    #[inline(always)]
+    fn cancel_writer_waiting(&self) {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while s & WRITER_WAITING != 0 && s != WRITE_LOCKED {
+            match self.state.compare_exchange(
+                s,
+                s - WRITER_WAITING,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(e) => s = e,
+            }
+        }
+
+        self.writer_wake_counter.fetch_add(1, Ordering::Release);
+    }
+}
+
+unsafe impl RawRwLock for WriterPreferring {
+    #[cfg(not(loom))]
+    const INIT: Self = Self {
+        state: AtomicUsize::new(0),
+        writer_wake_counter: AtomicUsize::new(0),
+    };
+
+    #[cfg(loom)]
+    fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            writer_wake_counter: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn lock_shared(&self) {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if can_read(s) {
+                match self.state.compare_exchange_weak(
+                    s,
+                    s + READER,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return,
+                    Err(e) => s = e,
+                }
+            }
+
+            if !can_read(s) {
+                crate::mwait::wait_while_equal(&self.state, s, Ordering::Relaxed);
+                s = self.state.load(Ordering::Relaxed);
+            }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock_shared(&self) -> bool {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while can_read(s) {
+            match self.state.compare_exchange_weak(
+                s,
+                s + READER,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(e) => s = e,
+            }
+        }
+
+        false
+    }
+
+    #[inline(always)]
+    fn lock_shared_until<F>(&self, mut cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if can_read(s) {
+                match self.state.compare_exchange_weak(
+                    s,
+                    s + READER,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return true,
+                    Err(e) => s = e,
+                }
+            }
+
+            if !can_read(s) {
+                if !crate::mwait::wait_while_equal_until(
+                    &self.state,
+                    s,
+                    Ordering::Relaxed,
+                    &mut cancel,
+                ) {
+                    return false;
+                }
+                s = self.state.load(Ordering::Relaxed);
+            }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    fn is_full_of_readers(&self) -> bool {
+        let s = self.state.load(Ordering::Relaxed);
+        s != WRITE_LOCKED && s / READER >= MAX_READERS
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_shared(&self) {
+        if self.state.fetch_sub(READER, Ordering::Release) == READER | WRITER_WAITING {
+            self.writer_wake_counter.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    #[inline(always)]
+    fn lock_exclusive(&self) {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if s <= WRITER_WAITING {
+                match self.state.compare_exchange(
+                    s,
+                    WRITE_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return,
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+
+            if s & WRITER_WAITING == 0 {
+                match self.state.compare_exchange(
+                    s,
+                    s + WRITER_WAITING,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => (),
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+
+            let w = self.writer_wake_counter.load(Ordering::Acquire);
+            s = self.state.load(Ordering::Relaxed);
+
+            // wait only while the writer waiting bit is set,
+            // because the last reader wakes writers up only in that case
+            if s > WRITER_WAITING && s & WRITER_WAITING != 0 {
+                crate::mwait::wait_while_equal(&self.writer_wake_counter, w, Ordering::Acquire);
+                s = self.state.load(Ordering::Relaxed);
+            }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock_exclusive(&self) -> bool {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while s <= WRITER_WAITING {
+            match self.state.compare_exchange_weak(
+                s,
+                WRITE_LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(e) => s = e,
+            }
+        }
+
+        false
+    }
+
+    #[inline(always)]
+    fn lock_exclusive_until<F>(&self, mut cancel: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if s <= WRITER_WAITING {
+                match self.state.compare_exchange(
+                    s,
+                    WRITE_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return true,
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+
+            if s & WRITER_WAITING == 0 {
+                match self.state.compare_exchange(
+                    s,
+                    s + WRITER_WAITING,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => (),
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+
+            let w = self.writer_wake_counter.load(Ordering::Acquire);
+            s = self.state.load(Ordering::Relaxed);
+
+            // wait only while the writer waiting bit is set,
+            // because the last reader wakes writers up only in that case
+            if s > WRITER_WAITING && s & WRITER_WAITING != 0 {
+                if !crate::mwait::wait_while_equal_until(
+                    &self.writer_wake_counter,
+                    w,
+                    Ordering::Acquire,
+                    &mut cancel,
+                ) {
+                    self.cancel_writer_waiting();
+                    return false;
+                }
+                s = self.state.load(Ordering::Relaxed);
+            }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_exclusive(&self) {
+        self.state.store(0, Ordering::Release);
+        self.writer_wake_counter.fetch_add(1, Ordering::Release);
+    }
+
+    #[inline(always)]
+    unsafe fn downgrade(&self) {
+        // waiting writers are woken up to set the writer waiting bit again,
+        // which was lost by `WRITE_LOCKED`
+        self.state.store(READER, Ordering::Release);
+        self.writer_wake_counter.fetch_add(1, Ordering::Release);
+    }
+}
+
+unsafe impl RawRwLockSend for WriterPreferring {}
+
+unsafe impl RawRwLockUpgrade for WriterPreferring {
+    #[inline(always)]
+    fn lock_upgradable(&self) {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if s & (WRITER_WAITING | UPGRADABLE) == 0 {
+                match self.state.compare_exchange_weak(
+                    s,
+                    s + UPGRADABLE,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return,
+                    Err(e) => s = e,
+                }
+            } else {
+                crate::mwait::wait_while_equal(&self.state, s, Ordering::Relaxed);
+                s = self.state.load(Ordering::Relaxed);
+            }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    fn try_lock_upgradable(&self) -> bool {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while s & (WRITER_WAITING | UPGRADABLE) == 0 {
+            match self.state.compare_exchange_weak(
+                s,
+                s + UPGRADABLE,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(e) => s = e,
+            }
+        }
+
+        false
+    }
+
+    #[inline(always)]
+    unsafe fn unlock_upgradable(&self) {
+        if self.state.fetch_sub(UPGRADABLE, Ordering::Release) == UPGRADABLE | WRITER_WAITING {
+            self.writer_wake_counter.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn upgrade(&self) {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if s & !WRITER_WAITING == UPGRADABLE {
+                match self.state.compare_exchange(
+                    s,
+                    WRITE_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return,
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+
+            // stop new readers, and wait for the current readers to leave
+            if s & WRITER_WAITING == 0 {
+                match self.state.compare_exchange(
+                    s,
+                    s + WRITER_WAITING,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => s += WRITER_WAITING,
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+
+            crate::mwait::wait_while_equal(&self.state, s, Ordering::Relaxed);
+            s = self.state.load(Ordering::Relaxed);
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn try_upgrade(&self) -> bool {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while s & !WRITER_WAITING == UPGRADABLE {
+            match self.state.compare_exchange_weak(
+                s,
+                WRITE_LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(e) => s = e,
+            }
+        }
+
+        false
+    }
+}
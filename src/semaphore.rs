@@ -0,0 +1,290 @@
+//! # Counting semaphore
+//!
+//! `Semaphore` hands out permits in FIFO order.
+//! A waiter takes a ticket like `TicketLock`, and only the waiter at the head of the queue takes permits,
+//! so that a waiter asking for many permits is not overtaken by waiters asking for fewer.
+//!
+//! Without `std`, waiters spin with the Monitor/MWAIT instructions if the `x86_mwait` feature is enabled.
+//! With `std`, waiters sleep on a condition variable.
+//!
+//! `release` and `try_acquire` never wait, so they can be called from interrupt handlers.
+//! `acquire` must not be called from interrupt handlers,
+//! because it waits with interrupts enabled for permits released by others.
+//!
+//! ```
+//! use awkernel_sync::semaphore::Semaphore;
+//!
+//! let semaphore = Semaphore::new(2);
+//!
+//! let permit = semaphore.acquire();
+//! assert_eq!(semaphore.available_permits(), 1);
+//! assert!(semaphore.try_acquire_many(2).is_none());
+//! drop(permit);
+//!
+//! let permits = semaphore.acquire_many(2);
+//! assert_eq!(permits.num_permits(), 2);
+//! ```
+
+#[cfg(not(loom))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct Semaphore {
+    permits: AtomicUsize,
+
+    /// The ticket taken by the next waiter.
+    next: AtomicUsize,
+
+    /// The ticket of the waiter at the head of the queue.
+    serving: AtomicUsize,
+
+    #[cfg(all(feature = "std", not(loom)))]
+    blocking: Blocking,
+}
+
+impl Semaphore {
+    #[cfg(not(loom))]
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            permits: AtomicUsize::new(permits),
+            next: AtomicUsize::new(0),
+            serving: AtomicUsize::new(0),
+            #[cfg(feature = "std")]
+            blocking: Blocking::new(),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            permits: AtomicUsize::new(permits),
+            next: AtomicUsize::new(0),
+            serving: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait for a permit.
+    #[inline(always)]
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Wait for `n` permits.
+    ///
+    /// The waiters behind this wait until `n` permits are available,
+    /// so `n` must not be greater than the total number of permits.
+    pub fn acquire_many(&self, n: usize) -> SemaphorePermit<'_> {
+        let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+
+        // wait until I become the head of the queue
+        loop {
+            let serving = self.serving.load(Ordering::Acquire);
+            if serving == ticket {
+                break;
+            }
+
+            self.wait_while_equal(&self.serving, serving);
+        }
+
+        let mut permits = self.permits.load(Ordering::Relaxed);
+        loop {
+            if permits < n {
+                self.wait_while_equal(&self.permits, permits);
+                permits = self.permits.load(Ordering::Relaxed);
+                continue;
+            }
+
+            match self.permits.compare_exchange_weak(
+                permits,
+                permits - n,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => permits = current,
+            }
+        }
+
+        self.serve_next();
+
+        SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        }
+    }
+
+    /// Try to take a permit without waiting.
+    #[inline(always)]
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Try to take `n` permits without waiting.
+    ///
+    /// This fails if others are waiting, even if `n` permits are available.
+    pub fn try_acquire_many(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        // become the head of the queue only if no one is waiting
+        let serving = self.serving.load(Ordering::Acquire);
+        if self
+            .next
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return None;
+        }
+
+        let mut permits = self.permits.load(Ordering::Relaxed);
+        let acquired = loop {
+            if permits < n {
+                break false;
+            }
+
+            match self.permits.compare_exchange_weak(
+                permits,
+                permits - n,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break true,
+                Err(current) => permits = current,
+            }
+        };
+
+        self.serve_next();
+
+        acquired.then_some(SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    /// Add a permit.
+    ///
+    /// This is usually called to return a permit given up by `SemaphorePermit::forget`,
+    /// e.g. by an interrupt handler signaling the completion of a request.
+    #[inline(always)]
+    pub fn release(&self) {
+        self.add_permits(1);
+    }
+
+    /// Return the number of permits available now.
+    #[inline(always)]
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of waiters which have taken their tickets in `acquire` or `acquire_many`.
+    #[inline(always)]
+    pub fn num_waiters(&self) -> usize {
+        let serving = self.serving.load(Ordering::Relaxed);
+        let next = self.next.load(Ordering::Relaxed);
+        next.wrapping_sub(serving)
+    }
+
+    #[inline(always)]
+    fn add_permits(&self, n: usize) {
+        self.permits.fetch_add(n, Ordering::Release);
+        self.wake();
+    }
+
+    /// Let the next waiter become the head of the queue.
+    #[inline(always)]
+    fn serve_next(&self) {
+        // only the head of the queue updates the ticket being served
+        let serving = self.serving.load(Ordering::Relaxed);
+        self.serving
+            .store(serving.wrapping_add(1), Ordering::Release);
+        self.wake();
+    }
+
+    #[cfg(not(all(feature = "std", not(loom))))]
+    #[inline(always)]
+    fn wait_while_equal(&self, val: &AtomicUsize, current: usize) {
+        crate::mwait::wait_while_equal(val, current, Ordering::Relaxed);
+    }
+
+    #[cfg(all(feature = "std", not(loom)))]
+    #[inline(always)]
+    fn wait_while_equal(&self, val: &AtomicUsize, current: usize) {
+        self.blocking.wait_while_equal(val, current);
+    }
+
+    #[cfg(not(all(feature = "std", not(loom))))]
+    #[inline(always)]
+    fn wake(&self) {}
+
+    #[cfg(all(feature = "std", not(loom)))]
+    #[inline(always)]
+    fn wake(&self) {
+        self.blocking.wake();
+    }
+}
+
+/// Waiters sleep on a condition variable instead of spinning.
+#[cfg(all(feature = "std", not(loom)))]
+struct Blocking {
+    mutex: parking_lot::Mutex<()>,
+    condvar: parking_lot::Condvar,
+}
+
+#[cfg(all(feature = "std", not(loom)))]
+impl Blocking {
+    const fn new() -> Self {
+        Blocking {
+            mutex: parking_lot::const_mutex(()),
+            condvar: parking_lot::Condvar::new(),
+        }
+    }
+
+    fn wait_while_equal(&self, val: &AtomicUsize, current: usize) {
+        let mut guard = self.mutex.lock();
+        while val.load(Ordering::Relaxed) == current {
+            self.condvar.wait(&mut guard);
+        }
+    }
+
+    fn wake(&self) {
+        // the mutex makes sure that a waiter is either sleeping or sees the new value
+        drop(self.mutex.lock());
+        self.condvar.notify_all();
+    }
+}
+
+/// Permits taken from a `Semaphore`.
+/// The permits are returned when this is dropped.
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl SemaphorePermit<'_> {
+    /// Return the number of permits held by this.
+    #[inline(always)]
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Give up the permits without returning them to the semaphore.
+    ///
+    /// They can be returned later by `Semaphore::release`.
+    #[inline(always)]
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
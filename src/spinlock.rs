@@ -1,12 +1,78 @@
 use core::{
-    cell::UnsafeCell,
     marker::PhantomData,
     ops::{Deref, DerefMut},
+};
+
+#[cfg(not(loom))]
+use core::{
+    cell::UnsafeCell,
+    hint,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+#[cfg(loom)]
+use loom::{
+    cell::UnsafeCell,
+    hint,
     sync::atomic::{AtomicBool, Ordering},
 };
 
+use crate::{
+    access::WriteAccess,
+    mcs::MCSNode,
+    mutex::{RawLock, RawLockSend},
+};
+
+/// How `SpinLock` waits after it fails to acquire the lock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backoff {
+    /// Retry after a `spin_loop` hint.
+    /// This is the default.
+    None,
+
+    /// Spin for 1, 2, 4, ... iterations between retries, up to `cap` iterations.
+    /// This reduces the traffic on the lock word under heavy contention.
+    /// `cap` of 0 is treated as 1, so that at least one `spin_loop` hint is issued.
+    Exponential { cap: u32 },
+
+    /// Wait while the lock word is set, using Monitor/MWAIT with the `x86_mwait` feature.
+    Mwait,
+}
+
+impl Backoff {
+    /// Wait once, and return the number of iterations for the next exponential backoff.
+    #[inline(always)]
+    fn wait(self, lock_var: &AtomicBool, spins: u32) -> u32 {
+        match self {
+            Backoff::None => {
+                hint::spin_loop();
+
+                #[cfg(loom)]
+                loom::thread::yield_now();
+
+                spins
+            }
+            Backoff::Exponential { cap } => {
+                for _ in 0..spins {
+                    hint::spin_loop();
+                }
+
+                #[cfg(loom)]
+                loom::thread::yield_now();
+
+                spins.saturating_mul(2).min(cap)
+            }
+            Backoff::Mwait => {
+                super::mwait::wait_while_true(lock_var);
+                spins
+            }
+        }
+    }
+}
+
 pub struct SpinLock<T> {
     lock_var: AtomicBool,
+    backoff: Backoff,
     data: UnsafeCell<T>,
 }
 
@@ -14,33 +80,104 @@ unsafe impl<T: Send> Sync for SpinLock<T> {}
 unsafe impl<T: Send> Send for SpinLock<T> {}
 
 impl<T> SpinLock<T> {
+    #[cfg(not(loom))]
     pub const fn new(v: T) -> Self {
         SpinLock {
             lock_var: AtomicBool::new(false),
+            backoff: Backoff::None,
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new(v: T) -> Self {
+        SpinLock {
+            lock_var: AtomicBool::new(false),
+            backoff: Backoff::None,
             data: UnsafeCell::new(v),
         }
     }
 
+    /// Set how the lock waits after it fails to acquire the lock.
+    ///
+    /// ```
+    /// use awkernel_sync::spinlock::{Backoff, SpinLock};
+    ///
+    /// let lock = SpinLock::new(0).with_backoff(Backoff::Exponential { cap: 64 });
+    /// *lock.lock() += 1;
+    /// ```
+    pub const fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = match backoff {
+            Backoff::Exponential { cap: 0 } => Backoff::Exponential { cap: 1 },
+            backoff => backoff,
+        };
+        self
+    }
+
     #[inline(always)]
-    pub fn try_lock(&self) -> Option<SpinLockGuard<T>> {
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
         let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
         if self
             .lock_var
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
         {
-            Some(SpinLockGuard {
-                spin_lock: self,
-                _interrupt_guard,
-                _phantom: PhantomData,
-            })
+            Some(SpinLockGuard::new(self, _interrupt_guard))
         } else {
             None
         }
     }
 
+    /// Acquire the lock without making a guard.
+    ///
+    /// Interrupts should be disabled while the lock is held.
+    #[inline(always)]
+    pub fn lock_raw(&self) {
+        let mut spins = 1;
+        loop {
+            if !self.lock_var.load(Ordering::Relaxed)
+                && self
+                    .lock_var
+                    .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                break;
+            }
+
+            spins = self.backoff.wait(&self.lock_var, spins);
+        }
+    }
+
+    /// Try to acquire the lock without making a guard.
+    ///
+    /// Interrupts should be disabled while the lock is held.
     #[inline(always)]
-    pub fn lock(&self) -> SpinLockGuard<T> {
+    pub fn try_lock_raw(&self) -> bool {
+        self.lock_var
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Release the lock acquired by `lock_raw` or `try_lock_raw`.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held by the caller.
+    #[inline(always)]
+    pub unsafe fn unlock_raw(&self) {
+        self.lock_var.store(false, Ordering::Release);
+    }
+
+    /// Return a pointer to the protected data.
+    #[cfg(not(loom))]
+    #[inline(always)]
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    #[inline(always)]
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let mut spins = 1;
         let _interrupt_guard = loop {
             if !self.lock_var.load(Ordering::Relaxed) {
                 let interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
@@ -52,25 +189,125 @@ impl<T> SpinLock<T> {
                     break interrupt_guard;
                 };
             }
+
+            spins = self.backoff.wait(&self.lock_var, spins);
         };
 
-        SpinLockGuard {
-            spin_lock: self,
-            _interrupt_guard,
-            _phantom: PhantomData,
-        }
+        SpinLockGuard::new(self, _interrupt_guard)
     }
 }
 
+unsafe impl RawLock for SpinLock<()> {
+    #[cfg(not(loom))]
+    const INIT: Self = SpinLock::new(());
+
+    #[cfg(loom)]
+    fn new() -> Self {
+        SpinLock::new(())
+    }
+
+    const USES_NODE: bool = false;
+
+    #[inline(always)]
+    unsafe fn lock(&self, _node: &mut MCSNode) {
+        self.lock_raw();
+    }
+
+    #[inline(always)]
+    unsafe fn try_lock(&self, _node: &mut MCSNode) -> bool {
+        self.try_lock_raw()
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self, _node: &MCSNode) {
+        self.unlock_raw();
+    }
+}
+
+unsafe impl RawLockSend for SpinLock<()> {}
+
 pub struct SpinLockGuard<'a, T> {
     spin_lock: &'a SpinLock<T>,
+    access: WriteAccess<T>,
     _interrupt_guard: crate::interrupt_guard::InterruptGuard,
     _phantom: PhantomData<*mut ()>,
 }
 
+impl<'a, T> SpinLockGuard<'a, T> {
+    #[inline(always)]
+    fn new(
+        spin_lock: &'a SpinLock<T>,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        SpinLockGuard {
+            spin_lock,
+            access: WriteAccess::start(&spin_lock.data),
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: Send> SpinLockGuard<'a, T> {
+    /// Make a guard for a part of the locked data.
+    ///
+    /// The lock is held until the returned guard is dropped.
+    /// This is an associated function to avoid conflicts with methods of `T`.
+    #[inline(always)]
+    pub fn map<U, F>(mut guard: Self, f: F) -> MappedSpinLockGuard<'a, T, U>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(&mut guard) as *mut U;
+        MappedSpinLockGuard {
+            _guard: guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<U, F>(mut guard: Self, f: F) -> Result<MappedSpinLockGuard<'a, T, U>, Self>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(&mut guard).map(|data| data as *mut U) {
+            Some(data) => Ok(MappedSpinLockGuard {
+                _guard: guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the locked data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<U, E, F>(
+        mut guard: Self,
+        f: F,
+    ) -> Result<MappedSpinLockGuard<'a, T, U>, (Self, E)>
+    where
+        U: ?Sized,
+        F: FnOnce(&mut T) -> Result<&mut U, E>,
+    {
+        match f(&mut guard).map(|data| data as *mut U) {
+            Ok(data) => Ok(MappedSpinLockGuard {
+                _guard: guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
 impl<T> Drop for SpinLockGuard<'_, T> {
     #[inline(always)]
     fn drop(&mut self) {
+        self.access.end();
         self.spin_lock.lock_var.store(false, Ordering::Release);
     }
 }
@@ -80,27 +317,102 @@ impl<T: Send> Deref for SpinLockGuard<'_, T> {
 
     #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        unsafe { &*self.spin_lock.data.get() }
+        unsafe { &*self.access.get(&self.spin_lock.data) }
     }
 }
 
 impl<T: Send> DerefMut for SpinLockGuard<'_, T> {
     #[inline(always)]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.spin_lock.data.get() }
+        unsafe { &mut *self.access.get(&self.spin_lock.data) }
     }
 }
 
 impl<T: Send> AsMut<T> for SpinLockGuard<'_, T> {
     #[inline(always)]
     fn as_mut(&mut self) -> &mut T {
-        unsafe { &mut *self.spin_lock.data.get() }
+        unsafe { &mut *self.access.get(&self.spin_lock.data) }
     }
 }
 
 impl<T: Send> AsRef<T> for SpinLockGuard<'_, T> {
     #[inline(always)]
     fn as_ref(&self) -> &T {
-        unsafe { &*self.spin_lock.data.get() }
+        unsafe { &*self.access.get(&self.spin_lock.data) }
+    }
+}
+
+/// A guard for a part of the data protected by `SpinLock`,
+/// made by `SpinLockGuard::map`, `filter_map` or `try_map`.
+///
+/// The lock and the interrupt guard are held by the original guard inside.
+pub struct MappedSpinLockGuard<'a, T, U: ?Sized> {
+    _guard: SpinLockGuard<'a, T>,
+    data: *mut U,
+}
+
+impl<'a, T, U: ?Sized> MappedSpinLockGuard<'a, T, U> {
+    /// Make a guard for a part of the mapped data.
+    #[inline(always)]
+    pub fn map<V, F>(guard: Self, f: F) -> MappedSpinLockGuard<'a, T, V>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> &mut V,
+    {
+        let data = f(unsafe { &mut *guard.data }) as *mut V;
+        MappedSpinLockGuard {
+            _guard: guard._guard,
+            data,
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Some`.
+    /// Otherwise, the original guard is returned.
+    #[inline(always)]
+    pub fn filter_map<V, F>(guard: Self, f: F) -> Result<MappedSpinLockGuard<'a, T, V>, Self>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> Option<&mut V>,
+    {
+        match f(unsafe { &mut *guard.data }).map(|data| data as *mut V) {
+            Some(data) => Ok(MappedSpinLockGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            None => Err(guard),
+        }
+    }
+
+    /// Make a guard for a part of the mapped data if `f` returns `Ok`.
+    /// Otherwise, the original guard and the error are returned.
+    #[inline(always)]
+    pub fn try_map<V, E, F>(guard: Self, f: F) -> Result<MappedSpinLockGuard<'a, T, V>, (Self, E)>
+    where
+        V: ?Sized,
+        F: FnOnce(&mut U) -> Result<&mut V, E>,
+    {
+        match f(unsafe { &mut *guard.data }).map(|data| data as *mut V) {
+            Ok(data) => Ok(MappedSpinLockGuard {
+                _guard: guard._guard,
+                data,
+            }),
+            Err(e) => Err((guard, e)),
+        }
+    }
+}
+
+impl<T, U: ?Sized> Deref for MappedSpinLockGuard<'_, T, U> {
+    type Target = U;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<T, U: ?Sized> DerefMut for MappedSpinLockGuard<'_, T, U> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data }
     }
 }
@@ -0,0 +1,258 @@
+//! # Ticket lock
+//!
+//! `TicketLock` serves waiters in FIFO order like `MCSLock`, but needs no node.
+//! A waiter takes a ticket and waits until the ticket is served.
+//! Because the waiter knows how many waiters are before it,
+//! it backs off in proportion to the distance instead of polling the lock word continuously.
+//!
+//! ```
+//! use awkernel_sync::ticket::TicketLock;
+//!
+//! let lock = TicketLock::new(0);
+//!
+//! let mut guard = lock.lock();
+//! *guard += 1;
+//! assert!(lock.try_lock().is_none());
+//! drop(guard);
+//!
+//! assert_eq!(*lock.try_lock().unwrap(), 1);
+//! ```
+
+use core::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+#[cfg(not(loom))]
+use core::{
+    cell::UnsafeCell,
+    hint,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(loom)]
+use loom::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    access::WriteAccess,
+    mcs::MCSNode,
+    mutex::{RawLock, RawLockSend},
+};
+
+/// The number of spins per waiter before the ticket of a waiter.
+#[cfg(not(loom))]
+const SPINS_PER_WAITER: usize = 64;
+
+pub struct TicketLock<T> {
+    /// The ticket taken by the next waiter.
+    next: AtomicUsize,
+
+    /// The ticket holding the lock.
+    serving: AtomicUsize,
+
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for TicketLock<T> {}
+unsafe impl<T: Send> Send for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    #[cfg(not(loom))]
+    pub const fn new(v: T) -> Self {
+        TicketLock {
+            next: AtomicUsize::new(0),
+            serving: AtomicUsize::new(0),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    #[cfg(loom)]
+    pub fn new(v: T) -> Self {
+        TicketLock {
+            next: AtomicUsize::new(0),
+            serving: AtomicUsize::new(0),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    /// acquire lock
+    #[inline(always)]
+    pub fn lock(&self) -> TicketLockGuard<'_, T> {
+        // interrupts must be disabled before taking a ticket,
+        // because the ticket cannot be given back
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        self.lock_raw();
+        TicketLockGuard::new(self, _interrupt_guard)
+    }
+
+    /// Try to acquire the lock without waiting.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T>> {
+        let _interrupt_guard = crate::interrupt_guard::InterruptGuard::new();
+        if self.try_lock_raw() {
+            Some(TicketLockGuard::new(self, _interrupt_guard))
+        } else {
+            None
+        }
+    }
+
+    /// Acquire the lock without making a guard.
+    ///
+    /// Interrupts should be disabled while the lock is held or waited for.
+    #[inline(always)]
+    pub fn lock_raw(&self) {
+        let ticket = self.next.fetch_add(1, Ordering::Relaxed);
+
+        loop {
+            let serving = self.serving.load(Ordering::Acquire);
+            if serving == ticket {
+                return;
+            }
+
+            // the waiters before me hold the lock one by one
+            #[cfg(not(loom))]
+            for _ in 0..ticket.wrapping_sub(serving) * SPINS_PER_WAITER {
+                hint::spin_loop();
+            }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        }
+    }
+
+    /// Try to acquire the lock without making a guard.
+    ///
+    /// Interrupts should be disabled while the lock is held.
+    #[inline(always)]
+    pub fn try_lock_raw(&self) -> bool {
+        // no ticket is waiting if the next ticket is being served
+        let serving = self.serving.load(Ordering::Acquire);
+        self.next
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    /// Release the lock acquired by `lock_raw` or `try_lock_raw`.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held by the caller.
+    #[inline(always)]
+    pub unsafe fn unlock_raw(&self) {
+        // only the holder updates the ticket being served
+        let serving = self.serving.load(Ordering::Relaxed);
+        self.serving
+            .store(serving.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Return `true` if others are waiting for the lock held by someone.
+    #[inline(always)]
+    pub fn is_contended(&self) -> bool {
+        self.num_waiters() > 0
+    }
+
+    /// Return the number of waiters which have taken their tickets.
+    #[inline(always)]
+    pub fn num_waiters(&self) -> usize {
+        let serving = self.serving.load(Ordering::Relaxed);
+        let next = self.next.load(Ordering::Relaxed);
+        next.wrapping_sub(serving).saturating_sub(1)
+    }
+}
+
+unsafe impl RawLock for TicketLock<()> {
+    #[cfg(not(loom))]
+    const INIT: Self = TicketLock::new(());
+
+    #[cfg(loom)]
+    fn new() -> Self {
+        TicketLock::new(())
+    }
+
+    const USES_NODE: bool = false;
+
+    #[inline(always)]
+    unsafe fn lock(&self, _node: &mut MCSNode) {
+        self.lock_raw();
+    }
+
+    #[inline(always)]
+    unsafe fn try_lock(&self, _node: &mut MCSNode) -> bool {
+        self.try_lock_raw()
+    }
+
+    #[inline(always)]
+    unsafe fn unlock(&self, _node: &MCSNode) {
+        self.unlock_raw();
+    }
+}
+
+unsafe impl RawLockSend for TicketLock<()> {}
+
+pub struct TicketLockGuard<'a, T> {
+    ticket_lock: &'a TicketLock<T>,
+    access: WriteAccess<T>,
+    _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    _phantom: PhantomData<*mut ()>,
+}
+
+impl<'a, T> TicketLockGuard<'a, T> {
+    #[inline(always)]
+    fn new(
+        ticket_lock: &'a TicketLock<T>,
+        _interrupt_guard: crate::interrupt_guard::InterruptGuard,
+    ) -> Self {
+        TicketLockGuard {
+            ticket_lock,
+            access: WriteAccess::start(&ticket_lock.data),
+            _interrupt_guard,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for TicketLockGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.access.end();
+        unsafe { self.ticket_lock.unlock_raw() };
+    }
+}
+
+impl<T: Send> Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.access.get(&self.ticket_lock.data) }
+    }
+}
+
+impl<T: Send> DerefMut for TicketLockGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.access.get(&self.ticket_lock.data) }
+    }
+}
+
+impl<T: Send> AsMut<T> for TicketLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.access.get(&self.ticket_lock.data) }
+    }
+}
+
+impl<T: Send> AsRef<T> for TicketLockGuard<'_, T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &T {
+        unsafe { &*self.access.get(&self.ticket_lock.data) }
+    }
+}
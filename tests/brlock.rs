@@ -0,0 +1,67 @@
+#[cfg(not(loom))]
+#[test]
+fn brlock() {
+    use awkernel_sync::brlock::BrLock;
+    use std::{sync::Arc, thread};
+
+    let lock = Arc::new(BrLock::new((0, 0)));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|i| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                for j in 0..num_iterations {
+                    if (i + j) % 4 == 0 {
+                        let mut guard = lock.write();
+                        guard.0 += 1;
+                        guard.1 += 1;
+                    } else {
+                        // nested readers on the same CPU
+                        let r1 = lock.read();
+                        let r2 = lock.try_read().unwrap();
+                        assert_eq!(r1.0, r2.1);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(lock.read().0, num_threads * num_iterations / 4);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn brlock_nested_read_with_waiting_writer() {
+    use awkernel_sync::brlock::{BrLock, BrLockWriteGuard};
+    use std::{sync::Arc, thread, time::Duration};
+
+    let lock = Arc::new(BrLock::new(0));
+    let r1 = lock.read();
+
+    let lock0 = lock.clone();
+    let writer = thread::spawn(move || {
+        let mut guard = lock0.write();
+        *guard += 1;
+        assert_eq!(*BrLockWriteGuard::downgrade(guard), 1);
+    });
+
+    // let the writer set the flag and wait for this CPU
+    thread::sleep(Duration::from_millis(20));
+
+    let r2 = lock.try_read().unwrap();
+    let r3 = lock.read();
+    assert_eq!(*r1 + *r2 + *r3, 0);
+    drop((r1, r2, r3));
+
+    writer.join().unwrap();
+    assert_eq!(lock.with_read(|data| *data), 1);
+
+    lock.with_write(|data| *data += 1);
+    assert_eq!(*lock.read(), 2);
+}
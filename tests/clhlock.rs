@@ -0,0 +1,77 @@
+#[cfg(not(loom))]
+#[test]
+fn clhlock() {
+    use awkernel_sync::clh::{CLHLock, CLHNode};
+    use std::{sync::Arc, thread};
+
+    let first = Arc::new(CLHLock::new(0));
+    let second = Arc::new(CLHLock::new(0));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let first = first.clone();
+            let second = second.clone();
+            thread::spawn(move || {
+                // a node is reused for both locks
+                let mut node = CLHNode::new();
+                for _ in 0..num_iterations {
+                    *first.lock(&mut node) += 1;
+                    *second.lock(&mut node) += 1;
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let mut node = CLHNode::new();
+    let guard = first.lock(&mut node);
+    assert_eq!(*guard, num_threads * num_iterations);
+
+    let mut node2 = CLHNode::new();
+    assert!(first.try_lock(&mut node2).is_none());
+    drop(guard);
+
+    assert_eq!(
+        *second.try_lock(&mut node).unwrap(),
+        num_threads * num_iterations
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn clhlock_reuse_taken_over_cell() {
+    use awkernel_sync::clh::{CLHLock, CLHNode};
+    use std::{sync::Arc, thread, time::Duration};
+
+    let lock = Arc::new(CLHLock::new(0));
+
+    let mut node = CLHNode::new();
+    let guard = lock.lock(&mut node);
+
+    let lock0 = lock.clone();
+    let thread = thread::spawn(move || {
+        // the node takes over the cell of the main thread,
+        // and keeps both cells after releasing the lock without a successor
+        let mut node = CLHNode::new();
+        *lock0.lock(&mut node) += 1;
+
+        // the left over cell is freed before the node is enqueued again
+        for _ in 0..3 {
+            *lock0.lock(&mut node) += 1;
+        }
+        node
+    });
+
+    // let the thread wait for the lock
+    thread::sleep(Duration::from_millis(20));
+    drop(guard);
+
+    let mut other = thread.join().unwrap();
+    assert_eq!(*lock.try_lock(&mut other).unwrap(), 4);
+    assert_eq!(*lock.lock(&mut node), 4);
+}
@@ -0,0 +1,93 @@
+#[cfg(not(loom))]
+thread_local! {
+    /// The cluster of the current thread if it is fixed by the test.
+    static CLUSTER: std::cell::Cell<Option<usize>> = const { std::cell::Cell::new(None) };
+}
+
+/// Even and odd CPUs are in different clusters unless the thread fixes its cluster.
+/// Every test registers this, because the tests share the registered function.
+#[cfg(not(loom))]
+fn cluster_of(cpu: usize) -> usize {
+    CLUSTER.with(|cluster| cluster.get()).unwrap_or(cpu % 2)
+}
+
+#[cfg(not(loom))]
+#[test]
+fn cohort() {
+    use awkernel_sync::{cohort::CohortLock, mcs::MCSNode};
+    use std::{sync::Arc, thread};
+
+    awkernel_sync::set_cluster_of_fn(cluster_of);
+
+    for max_handoffs in [0, 1, 64] {
+        let lock = Arc::new(CohortLock::new(0).with_max_handoffs(max_handoffs));
+        let num_threads = 4;
+        let num_iterations = 1000;
+
+        let threads: Vec<_> = (0..num_threads)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    for _ in 0..num_iterations {
+                        let mut node = MCSNode::new();
+                        *lock.lock(&mut node) += 1;
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let mut node = MCSNode::new();
+        let guard = lock.lock(&mut node);
+        assert_eq!(*guard, num_threads * num_iterations);
+
+        let mut node2 = MCSNode::new();
+        assert!(lock.try_lock(&mut node2).is_none());
+        drop(guard);
+
+        assert!(lock.try_lock(&mut node2).is_some());
+    }
+}
+
+#[cfg(not(loom))]
+#[test]
+fn cohort_handoff() {
+    use awkernel_sync::{cohort::CohortLock, mcs::MCSNode};
+    use std::{sync::Arc, thread, time::Duration};
+
+    awkernel_sync::set_cluster_of_fn(cluster_of);
+    CLUSTER.with(|cluster| cluster.set(Some(0)));
+
+    let lock = Arc::new(CohortLock::new(Vec::new()).with_max_handoffs(4));
+    let mut node = MCSNode::new();
+    let guard = lock.lock(&mut node);
+
+    // the waiters queue up in both clusters while the lock is held,
+    // and the first waiter of cluster 1 spins on the global lock
+    let threads: Vec<_> = (0..6)
+        .map(|i| {
+            let lock = lock.clone();
+            let thread = thread::spawn(move || {
+                CLUSTER.with(|cluster| cluster.set(Some(i % 2)));
+                let mut node = MCSNode::new();
+                lock.lock(&mut node).push(i);
+            });
+            thread::sleep(Duration::from_millis(20));
+            thread
+        })
+        .collect();
+
+    drop(guard);
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // each cluster passes the lock to all of its waiters before the other cluster,
+    // because the number of handoffs does not reach `max_handoffs`
+    let mut node = MCSNode::new();
+    assert_eq!(*lock.try_lock(&mut node).unwrap(), [0, 2, 4, 1, 3, 5]);
+}
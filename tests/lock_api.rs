@@ -0,0 +1,74 @@
+#[cfg(all(feature = "lock_api", not(loom)))]
+fn increment<R: lock_api::RawMutex + Send + Sync + 'static>() {
+    use std::{sync::Arc, thread};
+
+    let data = Arc::new(lock_api::Mutex::<R, _>::new(0));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let data = data.clone();
+            thread::spawn(move || {
+                for _ in 0..num_iterations {
+                    *data.lock() += 1;
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let guard = data.try_lock().unwrap();
+    assert_eq!(*guard, num_threads * num_iterations);
+    assert!(data.try_lock().is_none());
+}
+
+#[cfg(all(feature = "lock_api", not(loom)))]
+#[test]
+fn lock_api_spinlock() {
+    increment::<awkernel_sync::spinlock::SpinLock<()>>();
+}
+
+#[cfg(all(feature = "lock_api", not(loom)))]
+#[test]
+fn lock_api_mcslock() {
+    increment::<awkernel_sync::lock_api::PooledMCSLock>();
+
+    // nested locks borrow different nodes
+    let outer = awkernel_sync::lock_api::MCSMutex::new(0);
+    let inner = awkernel_sync::lock_api::MCSMutex::new(0);
+    let mut outer = outer.lock();
+    *inner.lock() += 1;
+    *outer += *inner.lock();
+    assert_eq!(*outer, 1);
+}
+
+#[cfg(all(feature = "lock_api", not(loom)))]
+#[test]
+fn lock_api_rwlock() {
+    use awkernel_sync::{lock_api::RwLock, rwlock::PhaseFairTicket};
+    use lock_api::{RwLockUpgradableReadGuard, RwLockWriteGuard};
+
+    let rwlock: RwLock<_> = RwLock::new(0);
+
+    let upgradable = rwlock.upgradable_read();
+    let reader = rwlock.read();
+    assert!(rwlock.try_write().is_none());
+    drop(reader);
+
+    let mut writer = RwLockUpgradableReadGuard::upgrade(upgradable);
+    *writer += 1;
+    let reader = RwLockWriteGuard::downgrade(writer);
+    assert_eq!(*reader, 1);
+    assert!(rwlock.try_read().is_some());
+    drop(reader);
+
+    let rwlock = RwLock::<_, PhaseFairTicket>::new(0);
+    *rwlock.write() += 1;
+    let r1 = rwlock.read();
+    let r2 = rwlock.try_read().unwrap();
+    assert_eq!(*r1 + *r2, 2);
+}
@@ -0,0 +1,37 @@
+#[cfg(not(loom))]
+#[test]
+fn mcs_rwlock() {
+    use awkernel_sync::mcs_rw::{MCSRwLock, MCSRwNode};
+    use std::{sync::Arc, thread};
+
+    let lock = Arc::new(MCSRwLock::new((0, 0)));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|i| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                let mut node = MCSRwNode::new();
+                for j in 0..num_iterations {
+                    if (i + j) % 4 == 0 {
+                        let mut guard = lock.write(&mut node);
+                        guard.0 += 1;
+                        guard.1 += 1;
+                    } else {
+                        let guard = lock.read(&mut node);
+                        assert_eq!(guard.0, guard.1);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let mut node = MCSRwNode::new();
+    let guard = lock.read(&mut node);
+    assert_eq!(guard.0, num_threads * num_iterations / 4);
+}
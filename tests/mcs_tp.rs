@@ -0,0 +1,109 @@
+//! A waiter is preempted by sleeping in the clock function,
+//! so that it stops publishing the time while it is in the queue.
+
+#[cfg(not(loom))]
+use std::{
+    cell::Cell,
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(not(loom))]
+std::thread_local! {
+    /// How many more times the clock is read before the thread is preempted.
+    static PREEMPT_AFTER: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+#[cfg(not(loom))]
+const PREEMPTION: Duration = Duration::from_millis(300);
+
+/// Return the uptime in microseconds, and sleep once if the current thread is set to be preempted.
+#[cfg(not(loom))]
+fn clock() -> u64 {
+    PREEMPT_AFTER.with(|preempt_after| match preempt_after.get() {
+        Some(0) => {
+            preempt_after.set(None);
+            thread::sleep(PREEMPTION);
+        }
+        Some(n) => preempt_after.set(Some(n - 1)),
+        None => (),
+    });
+
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mcs_tp_skip_preempted() {
+    use awkernel_sync::mcs_tp::{MCSTPLock, MCSTPNode};
+    use std::sync::Arc;
+
+    awkernel_sync::set_uptime_fn(clock);
+
+    let lock = Arc::new(MCSTPLock::new(Vec::new()).with_threshold(50_000));
+    let mut node = MCSTPNode::new();
+    let guard = lock.lock(&mut node);
+
+    // the first waiter is preempted while it is waiting in the queue
+    let lock0 = lock.clone();
+    let preempted = thread::spawn(move || {
+        PREEMPT_AFTER.with(|preempt_after| preempt_after.set(Some(1)));
+        let mut node = MCSTPNode::new();
+        lock0.lock(&mut node).push("preempted");
+    });
+    thread::sleep(Duration::from_millis(20));
+
+    let lock1 = lock.clone();
+    let running = thread::spawn(move || {
+        let mut node = MCSTPNode::new();
+        lock1.lock(&mut node).push("running");
+    });
+
+    // release the lock after the time of the preempted waiter gets older than the threshold
+    thread::sleep(Duration::from_millis(100));
+    drop(guard);
+
+    running.join().unwrap();
+    preempted.join().unwrap();
+
+    let mut node = MCSTPNode::new();
+    assert_eq!(*lock.try_lock(&mut node).unwrap(), ["running", "preempted"]);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mcs_tp() {
+    use awkernel_sync::mcs_tp::{MCSTPLock, MCSTPNode};
+    use std::sync::Arc;
+
+    awkernel_sync::set_uptime_fn(clock);
+
+    // running waiters are skipped too when the threshold is 0,
+    // but every waiter eventually acquires the lock
+    for threshold in [0, 1000] {
+        let lock = Arc::new(MCSTPLock::new(0).with_threshold(threshold));
+        let num_threads = 4;
+        let num_iterations = 1000;
+
+        let threads: Vec<_> = (0..num_threads)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    let mut node = MCSTPNode::new();
+                    for _ in 0..num_iterations {
+                        *lock.lock(&mut node) += 1;
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let mut node = MCSTPNode::new();
+        assert_eq!(*lock.lock(&mut node), num_threads * num_iterations);
+    }
+}
@@ -0,0 +1,78 @@
+#[cfg(not(loom))]
+#[test]
+fn mcslock_pooled() {
+    use awkernel_sync::mcs::MCSLock;
+    use std::{sync::Arc, thread};
+
+    let outer = Arc::new(MCSLock::new(0));
+    let inner = Arc::new(MCSLock::new(0));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let outer = outer.clone();
+            let inner = inner.clone();
+            thread::spawn(move || {
+                for _ in 0..num_iterations {
+                    let mut outer = outer.lock_pooled();
+                    let mut inner = inner.lock_pooled();
+                    *outer += 1;
+                    *inner += 1;
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(*outer.lock_pooled(), num_threads * num_iterations);
+    assert_eq!(
+        *inner.try_lock_pooled().unwrap(),
+        num_threads * num_iterations
+    );
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mcslock_map() {
+    use awkernel_sync::mcs::{MCSLock, MCSLockGuard, MCSNode, MappedMCSLockGuard};
+
+    let lock = MCSLock::new((0, vec![1]));
+
+    // the original guard is returned and still holds the lock
+    let mut node = MCSNode::new();
+    let guard = MCSLockGuard::filter_map(lock.lock(&mut node), |data| data.1.get_mut(1))
+        .err()
+        .unwrap();
+    let mut second = MCSNode::new();
+    assert!(lock.try_lock(&mut second).is_none());
+    drop(guard);
+
+    let mut node = MCSNode::new();
+    let (guard, e) = MCSLockGuard::try_map(lock.lock(&mut node), |data| data.1.get_mut(1).ok_or(1))
+        .err()
+        .unwrap();
+    assert_eq!(e, 1);
+    drop(guard);
+
+    let mut node = MCSNode::new();
+    let mut guard = MCSLockGuard::map(lock.lock(&mut node), |data| &mut data.1);
+    guard.push(2);
+    let guard = MappedMCSLockGuard::filter_map(guard, |data| data.get_mut(2))
+        .err()
+        .unwrap();
+    let (guard, ()) = MappedMCSLockGuard::try_map(guard, |data| data.get_mut(2).ok_or(()))
+        .err()
+        .unwrap();
+    let mut guard = MappedMCSLockGuard::try_map(guard, |data| data.get_mut(1).ok_or(()))
+        .ok()
+        .unwrap();
+    *guard += 1;
+    drop(guard);
+
+    let mut node = MCSNode::new();
+    assert_eq!(*lock.try_lock(&mut node).unwrap(), (0, vec![1, 3]));
+}
@@ -0,0 +1,38 @@
+#[cfg(loom)]
+#[test]
+fn model_check_clhlock() {
+    use awkernel_sync::clh::{CLHLock, CLHNode};
+    use loom::{sync::Arc, thread};
+
+    loom::model(|| {
+        let lock = Arc::new(CLHLock::new(0));
+        let num_threads = 2;
+        let num_iterations = 2;
+
+        let threads: Vec<_> = (0..num_threads)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    // cells move between nodes when the node is reused
+                    let mut node = CLHNode::new();
+                    for _ in 0..num_iterations {
+                        let mut guard = lock.lock(&mut node);
+                        guard.with_mut(|data| unsafe { *data += 1 });
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let mut node = CLHNode::new();
+        let data = lock
+            .try_lock(&mut node)
+            .unwrap()
+            .with_mut(|data| unsafe { *data });
+
+        assert_eq!(num_threads * num_iterations, data);
+    });
+}
@@ -0,0 +1,124 @@
+#[cfg(loom)]
+fn builder() -> loom::model::Builder {
+    let mut builder = loom::model::Builder::new();
+    builder.max_branches = 10_000;
+    builder.preemption_bound = Some(2);
+    builder
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_mcs_rwlock_reader_writer() {
+    use awkernel_sync::mcs_rw::{MCSRwLock, MCSRwNode};
+    use loom::{sync::Arc, thread};
+
+    builder().check(|| {
+        let n = Arc::new(MCSRwLock::new(0));
+
+        let n0 = n.clone();
+        let writer = thread::spawn(move || {
+            let mut node = MCSRwNode::new();
+            n0.write(&mut node).with_mut(|data| unsafe {
+                *data += 1;
+                *data += 1;
+            });
+        });
+
+        let mut node = MCSRwNode::new();
+        let data = n.read(&mut node).with(|data| unsafe { *data });
+        assert_eq!(data % 2, 0);
+
+        writer.join().unwrap();
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_mcs_rwlock_writers() {
+    use awkernel_sync::mcs_rw::{MCSRwLock, MCSRwNode};
+    use loom::{sync::Arc, thread};
+
+    builder().check(|| {
+        let n = Arc::new(MCSRwLock::new(0));
+
+        let n0 = n.clone();
+        let writer = thread::spawn(move || {
+            let mut node = MCSRwNode::new();
+            n0.write(&mut node).with_mut(|data| unsafe { *data += 1 });
+        });
+
+        let mut node = MCSRwNode::new();
+        n.write(&mut node).with_mut(|data| unsafe { *data += 1 });
+
+        writer.join().unwrap();
+
+        let data = n.read(&mut node).with(|data| unsafe { *data });
+        assert_eq!(data, 2);
+    });
+}
+
+/// Readers holding the lock together,
+/// one entering behind the other and one woken up by the other.
+#[cfg(loom)]
+#[test]
+fn model_check_mcs_rwlock_readers() {
+    use awkernel_sync::mcs_rw::{MCSRwLock, MCSRwNode};
+    use loom::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    builder().check(|| {
+        let n = Arc::new(MCSRwLock::new(0));
+        let entered = Arc::new(AtomicBool::new(false));
+
+        let n0 = n.clone();
+        let entered0 = entered.clone();
+        let reader = thread::spawn(move || {
+            let mut node = MCSRwNode::new();
+            let r = n0.read(&mut node);
+            entered0.store(true, Ordering::Release);
+            r.with(|data| unsafe { *data })
+        });
+
+        // wait for the other reader while holding the lock
+        let mut node = MCSRwNode::new();
+        let r = n.read(&mut node);
+        while !entered.load(Ordering::Acquire) {
+            thread::yield_now();
+        }
+        drop(r);
+
+        assert_eq!(reader.join().unwrap(), 0);
+
+        let mut w = n.write(&mut node);
+        w.with_mut(|data| unsafe { *data += 1 });
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_mcs_rwlock_deref() {
+    use awkernel_sync::mcs_rw::{MCSRwLock, MCSRwNode};
+    use loom::{sync::Arc, thread};
+
+    builder().check(|| {
+        let n = Arc::new(MCSRwLock::new(0));
+
+        let n0 = n.clone();
+        let writer = thread::spawn(move || {
+            let mut node = MCSRwNode::new();
+            let mut w = n0.write(&mut node);
+            *w += 1;
+            *w += 1;
+        });
+
+        let mut node = MCSRwNode::new();
+        assert_eq!(*n.read(&mut node) % 2, 0);
+
+        writer.join().unwrap();
+    });
+}
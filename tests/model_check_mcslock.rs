@@ -32,3 +32,77 @@ fn model_check_mcslock() {
         assert_eq!(num_threads * num_iterations, data);
     });
 }
+
+#[cfg(loom)]
+#[test]
+fn model_check_mcslock_abort() {
+    use awkernel_sync::{mcs::MCSLock, mutex::MCSNode};
+    use loom::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    let mut builder = loom::model::Builder::new();
+    builder.max_branches = 10_000;
+    builder.preemption_bound = Some(2);
+
+    builder.check(|| {
+        let lock = Arc::new(MCSLock::new(0));
+        let acquired = Arc::new(AtomicUsize::new(0));
+
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let lock = lock.clone();
+                let acquired = acquired.clone();
+                thread::spawn(move || {
+                    let mut node = MCSNode::new();
+                    if let Some(mut guard) = lock.lock_until(&mut node, || true) {
+                        guard.with_mut(|data| unsafe { *data += 1 });
+                        acquired.fetch_add(1, Ordering::Relaxed);
+                    };
+                })
+            })
+            .collect();
+
+        let mut node = MCSNode::new();
+        lock.lock(&mut node).with_mut(|data| unsafe { *data += 1 });
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let mut node = MCSNode::new();
+        let data = lock.lock(&mut node).with_mut(|data| unsafe { *data });
+
+        assert_eq!(acquired.load(Ordering::Relaxed) + 1, data);
+    });
+}
+
+/// The data is accessed through `Deref` and `DerefMut` as in production code.
+#[cfg(loom)]
+#[test]
+fn model_check_mcslock_deref() {
+    use awkernel_sync::mcs::{MCSLock, MCSLockGuard, MCSNode};
+    use loom::{sync::Arc, thread};
+
+    loom::model(|| {
+        let lock = Arc::new(MCSLock::new((0, 0)));
+
+        let lock0 = lock.clone();
+        let t = thread::spawn(move || {
+            let mut node = MCSNode::new();
+            let mut guard = MCSLockGuard::map(lock0.lock(&mut node), |data| &mut data.0);
+            *guard += 1;
+        });
+
+        let mut node = MCSNode::new();
+        lock.lock(&mut node).1 += 1;
+
+        t.join().unwrap();
+
+        assert_eq!(*lock.lock(&mut node), (1, 1));
+    });
+}
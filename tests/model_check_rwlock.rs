@@ -50,3 +50,294 @@ fn model_check_rwlock() {
         }
     });
 }
+
+#[cfg(loom)]
+#[test]
+fn model_check_rwlock_write_until() {
+    use awkernel_sync::rwlock;
+    use loom::{sync::Arc, thread};
+
+    let mut builder = loom::model::Builder::new();
+    builder.max_branches = 10_000;
+    builder.preemption_bound = Some(2);
+
+    builder.check(|| {
+        let n = Arc::new(rwlock::RwLock::new(0));
+
+        let n0 = n.clone();
+        let canceled = thread::spawn(move || {
+            if let Some(mut w) = n0.write_until(|| true) {
+                w.with_mut(|data| unsafe { *data += 1 });
+            }
+        });
+
+        let n0 = n.clone();
+        let writer = thread::spawn(move || {
+            n0.write().with_mut(|data| unsafe { *data += 1 });
+        });
+
+        let r = n.read();
+        drop(r);
+
+        canceled.join().unwrap();
+        writer.join().unwrap();
+
+        let data = n.read().with(|data| unsafe { *data });
+        assert!(data == 1 || data == 2);
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_rwlock_upgrade_reader() {
+    use awkernel_sync::rwlock::{self, RwLockUpgradableGuard};
+    use loom::{sync::Arc, thread};
+
+    let mut builder = loom::model::Builder::new();
+    builder.max_branches = 10_000;
+    builder.preemption_bound = Some(2);
+
+    builder.check(|| {
+        let n = Arc::new(rwlock::RwLock::new(0));
+
+        let n0 = n.clone();
+        let upgrader = thread::spawn(move || {
+            let u = n0.upgradable_read();
+            let data = u.with(|data| unsafe { *data });
+            let mut w = RwLockUpgradableGuard::upgrade(u);
+            w.with_mut(|p| unsafe { *p = data + 1 });
+        });
+
+        let data = n.read().with(|data| unsafe { *data });
+        assert!(data <= 1);
+
+        upgrader.join().unwrap();
+
+        let data = n.read().with(|data| unsafe { *data });
+        assert_eq!(data, 1);
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_rwlock_upgrade_writer() {
+    use awkernel_sync::rwlock::{self, RwLockUpgradableGuard};
+    use loom::{sync::Arc, thread};
+
+    let mut builder = loom::model::Builder::new();
+    builder.max_branches = 10_000;
+    builder.preemption_bound = Some(2);
+
+    builder.check(|| {
+        let n = Arc::new(rwlock::RwLock::new(0));
+
+        let n0 = n.clone();
+        let upgrader = thread::spawn(move || {
+            let u = n0.upgradable_read();
+            let data = u.with(|data| unsafe { *data });
+            let mut w = RwLockUpgradableGuard::upgrade(u);
+            w.with_mut(|p| unsafe { *p = data + 1 });
+        });
+
+        let n0 = n.clone();
+        let writer = thread::spawn(move || {
+            n0.write().with_mut(|data| unsafe { *data += 1 });
+        });
+
+        upgrader.join().unwrap();
+        writer.join().unwrap();
+
+        let data = n.read().with(|data| unsafe { *data });
+        assert_eq!(data, 2);
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_rwlock_downgrade() {
+    use awkernel_sync::rwlock::{self, RwLockWriteGuard};
+    use loom::{sync::Arc, thread};
+
+    let mut builder = loom::model::Builder::new();
+    builder.max_branches = 10_000;
+    builder.preemption_bound = Some(2);
+
+    builder.check(|| {
+        let n = Arc::new(rwlock::RwLock::new(0));
+
+        let n0 = n.clone();
+        let writer = thread::spawn(move || {
+            n0.write().with_mut(|data| unsafe { *data += 10 });
+        });
+
+        let mut w = n.write();
+        let data = w.with_mut(|data| unsafe {
+            *data += 1;
+            *data
+        });
+
+        // the other writer must not get in before the downgraded guard is dropped
+        let r = RwLockWriteGuard::downgrade(w);
+        assert_eq!(r.with(|data| unsafe { *data }), data);
+        drop(r);
+
+        writer.join().unwrap();
+
+        let data = n.read().with(|data| unsafe { *data });
+        assert_eq!(data, 11);
+    });
+}
+
+#[cfg(loom)]
+fn model_check_policy<P: awkernel_sync::rwlock::RawRwLock + 'static>() {
+    use awkernel_sync::rwlock;
+    use loom::{sync::Arc, thread};
+
+    let mut builder = loom::model::Builder::new();
+    builder.max_branches = 10_000;
+    builder.preemption_bound = Some(2);
+
+    // a reader and a writer
+    builder.check(|| {
+        let n = Arc::new(rwlock::RwLock::<_, P>::with_policy(0));
+
+        let n0 = n.clone();
+        let writer = thread::spawn(move || {
+            n0.write().with_mut(|data| unsafe {
+                *data += 1;
+                *data += 1;
+            });
+        });
+
+        let data = n.read().with(|data| unsafe { *data });
+        assert_eq!(data % 2, 0);
+
+        writer.join().unwrap();
+    });
+
+    // writers
+    builder.check(|| {
+        let n = Arc::new(rwlock::RwLock::<_, P>::with_policy(0));
+
+        let writers: Vec<_> = (0..2)
+            .map(|_| {
+                let n0 = n.clone();
+                thread::spawn(move || {
+                    n0.write().with_mut(|data| unsafe { *data += 1 });
+                })
+            })
+            .collect();
+
+        for t in writers {
+            t.join().unwrap();
+        }
+
+        let data = n.read().with(|data| unsafe { *data });
+        assert_eq!(data, 2);
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_rwlock_reader_preferring() {
+    model_check_policy::<awkernel_sync::rwlock::ReaderPreferring>();
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_rwlock_phase_fair_ticket() {
+    model_check_policy::<awkernel_sync::rwlock::PhaseFairTicket>();
+}
+
+/// Drive the reader count to `MAX_READERS`, which is narrowed under loom.
+#[cfg(loom)]
+fn model_check_overflow<P: awkernel_sync::rwlock::RawRwLock + 'static>() {
+    use awkernel_sync::rwlock::{ReaderOverflow, RwLock, MAX_READERS};
+    use loom::{sync::Arc, thread};
+
+    let mut builder = loom::model::Builder::new();
+    builder.max_branches = 10_000;
+    builder.preemption_bound = Some(2);
+
+    // a reader waits until another reader leaves
+    builder.check(|| {
+        let n = Arc::new(RwLock::<_, P>::with_policy(0).with_reader_overflow(ReaderOverflow::Wait));
+
+        let mut guards: Vec<_> = (0..MAX_READERS).map(|_| n.read()).collect();
+        assert!(n.try_read().is_none());
+
+        let n0 = n.clone();
+        let reader = thread::spawn(move || n0.read().with(|data| unsafe { *data }));
+
+        guards.pop();
+        assert_eq!(reader.join().unwrap(), 0);
+
+        drop(guards);
+        n.write().with_mut(|data| unsafe { *data += 1 });
+    });
+
+    // try-style acquisitions fail
+    builder.check(|| {
+        let n = RwLock::<_, P>::with_policy(0).with_reader_overflow(ReaderOverflow::Fail);
+
+        let guards: Vec<_> = (0..MAX_READERS).map(|_| n.read()).collect();
+        assert!(n.try_read().is_none());
+        assert!(n.read_until(|| false).is_none());
+
+        drop(guards);
+        assert!(n.try_read().is_some());
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_rwlock_overflow() {
+    model_check_overflow::<awkernel_sync::rwlock::WriterPreferring>();
+    model_check_overflow::<awkernel_sync::rwlock::ReaderPreferring>();
+    model_check_overflow::<awkernel_sync::rwlock::PhaseFairTicket>();
+}
+
+#[cfg(loom)]
+#[test]
+#[should_panic(expected = "MAX_READERS")]
+fn model_check_rwlock_overflow_panic() {
+    use awkernel_sync::rwlock::{RwLock, MAX_READERS};
+
+    loom::model(|| {
+        let n = RwLock::new(0);
+
+        let _guards: Vec<_> = (0..MAX_READERS).map(|_| n.read()).collect();
+        let _ = n.read();
+    });
+}
+
+/// The data is accessed through `Deref` and `DerefMut` as in production code.
+#[cfg(loom)]
+#[test]
+fn model_check_rwlock_deref() {
+    use awkernel_sync::rwlock::{RwLock, RwLockWriteGuard};
+    use loom::{sync::Arc, thread};
+
+    let mut builder = loom::model::Builder::new();
+    builder.max_branches = 10_000;
+    builder.preemption_bound = Some(2);
+
+    builder.check(|| {
+        let n = Arc::new(RwLock::new(0));
+
+        let n0 = n.clone();
+        let writer = thread::spawn(move || {
+            let mut w = n0.write();
+            *w += 1;
+            *w += 1;
+
+            let r = RwLockWriteGuard::downgrade(w);
+            assert_eq!(*r % 2, 0);
+        });
+
+        assert_eq!(*n.read() % 2, 0);
+
+        writer.join().unwrap();
+        assert_eq!(*n.read(), 2);
+    });
+}
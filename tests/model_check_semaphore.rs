@@ -0,0 +1,36 @@
+#[cfg(loom)]
+#[test]
+fn model_check_semaphore() {
+    use awkernel_sync::semaphore::Semaphore;
+    use loom::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    loom::model(|| {
+        let semaphore = Arc::new(Semaphore::new(1));
+        let holders = Arc::new(AtomicUsize::new(0));
+
+        let semaphore0 = semaphore.clone();
+        let holders0 = holders.clone();
+        let t = thread::spawn(move || {
+            let _permit = semaphore0.acquire();
+            assert_eq!(holders0.fetch_add(1, Ordering::Relaxed), 0);
+            holders0.fetch_sub(1, Ordering::Relaxed);
+        });
+
+        if let Some(permit) = semaphore.try_acquire() {
+            assert_eq!(holders.fetch_add(1, Ordering::Relaxed), 0);
+            holders.fetch_sub(1, Ordering::Relaxed);
+            permit.forget();
+            semaphore.release();
+        }
+
+        t.join().unwrap();
+
+        assert_eq!(semaphore.available_permits(), 1);
+    });
+}
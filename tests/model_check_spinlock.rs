@@ -0,0 +1,52 @@
+#[cfg(loom)]
+#[test]
+fn model_check_spinlock() {
+    use awkernel_sync::spinlock::{SpinLock, SpinLockGuard};
+    use loom::{sync::Arc, thread};
+
+    loom::model(|| {
+        let lock = Arc::new(SpinLock::new((0, 0)));
+
+        let lock0 = lock.clone();
+        let t = thread::spawn(move || {
+            let mut guard = SpinLockGuard::map(lock0.lock(), |data| &mut data.0);
+            *guard += 1;
+        });
+
+        if let Some(mut guard) = lock.try_lock() {
+            guard.1 += 1;
+        } else {
+            lock.lock().1 += 1;
+        }
+
+        t.join().unwrap();
+
+        assert_eq!(*lock.lock(), (1, 1));
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn model_check_spinlock_backoff() {
+    use awkernel_sync::spinlock::{Backoff, SpinLock};
+    use loom::{sync::Arc, thread};
+
+    for backoff in [Backoff::Exponential { cap: 4 }, Backoff::Mwait] {
+        loom::model(move || {
+            let lock = Arc::new(SpinLock::new(0).with_backoff(backoff));
+
+            let lock0 = lock.clone();
+            let t = thread::spawn(move || {
+                *lock0.lock() += 1;
+            });
+
+            lock.lock_raw();
+            unsafe { lock.unlock_raw() };
+            *lock.lock() += 1;
+
+            t.join().unwrap();
+
+            assert_eq!(*lock.lock(), 2);
+        });
+    }
+}
@@ -0,0 +1,26 @@
+#[cfg(loom)]
+#[test]
+fn model_check_ticketlock() {
+    use awkernel_sync::ticket::TicketLock;
+    use loom::{sync::Arc, thread};
+
+    loom::model(|| {
+        let lock = Arc::new(TicketLock::new(0));
+
+        let lock0 = lock.clone();
+        let t = thread::spawn(move || {
+            *lock0.lock() += 1;
+        });
+
+        if let Some(mut guard) = lock.try_lock() {
+            *guard += 1;
+        } else {
+            *lock.lock() += 1;
+        }
+
+        t.join().unwrap();
+
+        assert_eq!(*lock.lock(), 2);
+        assert!(!lock.is_contended());
+    });
+}
@@ -0,0 +1,228 @@
+#[cfg(not(loom))]
+#[test]
+fn mutex_arc() {
+    use awkernel_sync::mutex::{ArcLockGuard, MCSNode, Mutex};
+    use std::{sync::Arc, thread};
+
+    let data = Arc::new(Mutex::new(0));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let data = data.clone();
+            thread::spawn(move || {
+                // the guards have no lifetime and can be kept in a struct
+                let mut guards = Vec::new();
+                for _ in 0..num_iterations {
+                    let mut guard = data.lock_arc();
+                    *guard += 1;
+                    guards.push(guard);
+                    guards.clear();
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let guard = data.lock_arc();
+    assert!(Arc::ptr_eq(ArcLockGuard::mutex(&guard), &data));
+    assert_eq!(*guard, num_threads * num_iterations);
+
+    let mut node = MCSNode::new();
+    assert!(data.try_lock(&mut node).is_none());
+}
+
+#[cfg(not(loom))]
+fn send_arc_guard<L: awkernel_sync::mutex::RawLockSend + 'static>() {
+    use awkernel_sync::mutex::{MCSNode, Mutex};
+    use std::{sync::Arc, thread};
+
+    let data = Arc::new(Mutex::<_, L>::with_raw_lock(0));
+
+    // the guard is released by another thread
+    let mut guard = data.lock_arc();
+    *guard += 1;
+    thread::spawn(move || {
+        *guard += 1;
+    })
+    .join()
+    .unwrap();
+
+    let mut node = MCSNode::new();
+    assert_eq!(*data.try_lock(&mut node).unwrap(), 2);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_arc_send() {
+    send_arc_guard::<awkernel_sync::mcs::MCSLock<()>>();
+    send_arc_guard::<awkernel_sync::spinlock::SpinLock<()>>();
+    send_arc_guard::<awkernel_sync::mutex::DefaultLock>();
+}
+
+#[cfg(not(loom))]
+fn increment<L: awkernel_sync::mutex::RawLock + 'static>() {
+    use awkernel_sync::mutex::{MCSNode, Mutex, MutexGuard};
+    use std::{sync::Arc, thread};
+
+    let data = Arc::new(Mutex::<_, L>::with_raw_lock((0, 0)));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let data = data.clone();
+            thread::spawn(move || {
+                for _ in 0..num_iterations {
+                    let mut node = MCSNode::new();
+                    data.lock(&mut node).0 += 1;
+
+                    let mut second = MutexGuard::map(data.lock_pooled(), |data| &mut data.1);
+                    *second += 1;
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let mut node = MCSNode::new();
+    let guard = data.try_lock(&mut node).unwrap();
+    assert_eq!(
+        *guard,
+        (num_threads * num_iterations, num_threads * num_iterations)
+    );
+    assert!(data.try_lock_pooled().is_none());
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_map() {
+    use awkernel_sync::mutex::{MCSNode, MappedMutexGuard, Mutex, MutexGuard};
+
+    let data = Mutex::new((0, vec![1]));
+
+    // the original guard is returned and still holds the lock
+    let guard = MutexGuard::filter_map(data.lock_pooled(), |data| data.1.get_mut(1))
+        .err()
+        .unwrap();
+    assert!(data.try_lock_pooled().is_none());
+    drop(guard);
+
+    let (guard, e) = MutexGuard::try_map(data.lock_pooled(), |data| data.1.get_mut(1).ok_or(1))
+        .err()
+        .unwrap();
+    assert_eq!(e, 1);
+    drop(guard);
+
+    let mut node = MCSNode::new();
+    let guard = MutexGuard::map(data.lock(&mut node), |data| &mut data.1);
+    let guard = MappedMutexGuard::filter_map(guard, |data| data.get_mut(1))
+        .err()
+        .unwrap();
+    let (guard, ()) = MappedMutexGuard::try_map(guard, |data| data.get_mut(1).ok_or(()))
+        .err()
+        .unwrap();
+    let mut guard = MappedMutexGuard::map(guard, |data| &mut data[0]);
+    *guard += 1;
+    assert!(data.try_lock_pooled().is_none());
+    drop(guard);
+
+    assert_eq!(*data.try_lock_pooled().unwrap(), (0, vec![2]));
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_mcslock() {
+    increment::<awkernel_sync::mcs::MCSLock<()>>();
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_spinlock() {
+    increment::<awkernel_sync::spinlock::SpinLock<()>>();
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_ticketlock() {
+    increment::<awkernel_sync::ticket::TicketLock<()>>();
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_qspinlock() {
+    increment::<awkernel_sync::qspinlock::QSpinLock<()>>();
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_cohort() {
+    increment::<awkernel_sync::cohort::CohortLock<()>>();
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_default() {
+    increment::<awkernel_sync::mutex::DefaultLock>();
+}
+
+#[cfg(not(loom))]
+fn drop_node<L: awkernel_sync::mutex::RawLock>() {
+    use awkernel_sync::mutex::{MCSNode, Mutex};
+
+    let data = Mutex::<_, L>::with_raw_lock(0);
+
+    // with std, the guard does not borrow the node
+    let mut guard = {
+        let mut node = MCSNode::new();
+        data.lock(&mut node)
+    };
+    *guard += 1;
+
+    let mut node = MCSNode::new();
+    assert!(data.try_lock(&mut node).is_none());
+    drop(guard);
+
+    let guard = data.try_lock(&mut MCSNode::new()).unwrap();
+    assert_eq!(*guard, 1);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_drop_node() {
+    drop_node::<awkernel_sync::mcs::MCSLock<()>>();
+    drop_node::<awkernel_sync::mutex::DefaultLock>();
+}
+
+#[cfg(not(loom))]
+#[test]
+fn mutex_with_lock() {
+    use awkernel_sync::{
+        mutex::{MCSNode, Mutex},
+        spinlock::SpinLock,
+    };
+
+    let data = Mutex::new(vec![1]);
+    data.with_lock(|data| data.push(2));
+    assert_eq!(data.try_with_lock(|data| data.len()), Some(2));
+
+    let mut node = MCSNode::new();
+    let guard = data.lock(&mut node);
+    assert_eq!(data.try_with_lock(|data| data.len()), None);
+    drop(guard);
+
+    // closures can be nested across backends
+    let spin = Mutex::<_, SpinLock<()>>::with_raw_lock(0);
+    let sum = spin.with_lock(|spin| {
+        *spin += 1;
+        data.with_lock(|data| data.iter().sum::<i32>() + *spin)
+    });
+    assert_eq!(sum, 4);
+}
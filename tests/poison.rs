@@ -0,0 +1,116 @@
+#[cfg(not(loom))]
+#[test]
+fn poison_mutex() {
+    use awkernel_sync::{
+        mutex::MCSNode,
+        poison::{Mutex, PoisonError, TryLockError},
+    };
+    use std::{sync::Arc, thread};
+
+    let data = Arc::new(Mutex::new(0));
+
+    let data2 = data.clone();
+    let result = thread::spawn(move || {
+        let mut guard = data2.lock_pooled().unwrap();
+        *guard += 1;
+        panic!("poison the lock");
+    })
+    .join();
+    assert!(result.is_err());
+    assert!(data.is_poisoned());
+
+    // the lock is released and the data is still accessible
+    let mut node = MCSNode::new();
+    assert!(data.lock_pooled().is_err());
+    let mut guard = data.lock(&mut node).unwrap_or_else(PoisonError::into_inner);
+    assert_eq!(*guard, 1);
+    *guard = 0;
+
+    let mut node2 = MCSNode::new();
+    assert!(matches!(
+        data.try_lock(&mut node2),
+        Err(TryLockError::WouldBlock)
+    ));
+    drop(guard);
+
+    let mut node = MCSNode::new();
+    assert!(matches!(
+        data.try_lock(&mut node),
+        Err(TryLockError::Poisoned(_))
+    ));
+
+    data.clear_poison();
+    let mut node = MCSNode::new();
+    assert_eq!(*data.try_lock(&mut node).unwrap(), 0);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn poison_rwlock() {
+    use awkernel_sync::poison::{PoisonError, RwLock};
+    use std::{sync::Arc, thread};
+
+    let lock = Arc::new(RwLock::new(0));
+
+    // a panicking reader does not poison the lock
+    let lock2 = lock.clone();
+    let result = thread::spawn(move || {
+        let _r = lock2.read().unwrap();
+        panic!("no poison");
+    })
+    .join();
+    assert!(result.is_err());
+    assert!(!lock.is_poisoned());
+
+    let lock2 = lock.clone();
+    let result = thread::spawn(move || {
+        *lock2.write().unwrap() += 1;
+        let _w = lock2.write().unwrap();
+        panic!("poison the lock");
+    })
+    .join();
+    assert!(result.is_err());
+    assert!(lock.is_poisoned());
+
+    assert_eq!(*lock.read().unwrap_or_else(PoisonError::into_inner), 1);
+    assert!(lock.try_write().is_err());
+
+    lock.clear_poison();
+    assert_eq!(*lock.try_read().unwrap(), 1);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn poison_spinlock() {
+    use awkernel_sync::poison::SpinLock;
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        sync::Arc,
+    };
+
+    let lock = Arc::new(SpinLock::new(0));
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _guard = lock.lock().unwrap();
+        panic!("poison the lock");
+    }));
+    assert!(result.is_err());
+    assert!(lock.is_poisoned());
+
+    // a lock acquired while unwinding is not poisoned by the same panic
+    let other = Arc::new(SpinLock::new(0));
+    struct LockOnDrop(Arc<SpinLock<i32>>);
+    impl Drop for LockOnDrop {
+        fn drop(&mut self) {
+            *self.0.lock().unwrap() += 1;
+        }
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _lock_on_drop = LockOnDrop(other.clone());
+        panic!("unwind");
+    }));
+    assert!(result.is_err());
+    assert!(!other.is_poisoned());
+    assert_eq!(*other.try_lock().unwrap(), 1);
+}
@@ -0,0 +1,69 @@
+#[cfg(not(loom))]
+#[test]
+fn qspinlock() {
+    use awkernel_sync::qspinlock::QSpinLock;
+    use std::{sync::Arc, thread};
+
+    let outer = Arc::new(QSpinLock::new(0));
+    let inner = Arc::new(QSpinLock::new(0));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let outer = outer.clone();
+            let inner = inner.clone();
+            thread::spawn(move || {
+                for _ in 0..num_iterations {
+                    let mut outer = outer.lock();
+                    *outer += 1;
+                    *inner.lock() += 1;
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let guard = outer.lock();
+    assert_eq!(*guard, num_threads * num_iterations);
+    assert!(outer.is_locked());
+    assert!(outer.try_lock().is_none());
+    drop(guard);
+
+    assert!(!outer.is_locked());
+    assert_eq!(*inner.try_lock().unwrap(), num_threads * num_iterations);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn qspinlock_queue() {
+    use awkernel_sync::qspinlock::QSpinLock;
+    use std::{sync::Arc, thread, time::Duration};
+
+    let lock = Arc::new(QSpinLock::new(Vec::new()));
+    let guard = lock.lock();
+
+    // the first waiter sets the pending bit, and the others wait in the queue
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let lock = lock.clone();
+            let thread = thread::spawn(move || lock.lock().push(i));
+            thread::sleep(Duration::from_millis(20));
+            thread
+        })
+        .collect();
+
+    assert!(lock.try_lock().is_none());
+    drop(guard);
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let mut result = lock.try_lock().unwrap().clone();
+    result.sort();
+    assert_eq!(result, [0, 1, 2, 3]);
+}
@@ -0,0 +1,293 @@
+#[cfg(not(loom))]
+#[test]
+fn rwlock_arc() {
+    use awkernel_sync::rwlock::{ArcRwLockWriteGuard, RwLock};
+    use std::{sync::Arc, thread};
+
+    let lock = Arc::new(RwLock::new(0));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                // the guards have no lifetime and can be kept in a struct
+                let mut guards = Vec::new();
+                for _ in 0..num_iterations {
+                    let mut guard = lock.write_arc();
+                    *guard += 1;
+                    guards.push(guard);
+                    guards.clear();
+                    assert!(*lock.read_arc() > 0);
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let guard = lock.write_arc();
+    assert!(Arc::ptr_eq(ArcRwLockWriteGuard::rwlock(&guard), &lock));
+    assert_eq!(*guard, num_threads * num_iterations);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rwlock_arc_send() {
+    use awkernel_sync::rwlock::RwLock;
+    use std::{sync::Arc, thread};
+
+    let lock = Arc::new(RwLock::new(0));
+
+    // the guards are released by another thread
+    let mut guard = lock.write_arc();
+    *guard += 1;
+    thread::spawn(move || {
+        *guard += 1;
+    })
+    .join()
+    .unwrap();
+
+    let guard = lock.read_arc();
+    let value = thread::spawn(move || *guard).join().unwrap();
+    assert_eq!(value, 2);
+    assert_eq!(*lock.try_write().unwrap(), 2);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rwlock_try_and_timeout() {
+    use awkernel_sync::rwlock::RwLock;
+
+    let lock = RwLock::new(0);
+
+    {
+        let r = lock.try_read().unwrap();
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+        assert!(lock.write_timeout(0).is_none());
+        assert_eq!(*r, 0);
+    }
+
+    {
+        let mut w = lock.try_write().unwrap();
+        assert!(lock.try_read().is_none());
+        assert!(lock.read_timeout(0).is_none());
+        assert!(lock.write_until(|| true).is_none());
+        *w += 1;
+    }
+
+    // the canceled writer must not block readers
+    assert_eq!(*lock.try_read().unwrap(), 1);
+    assert_eq!(*lock.write(), 1);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rwlock_upgradable() {
+    use awkernel_sync::rwlock::{RwLock, RwLockUpgradableGuard};
+
+    let lock = RwLock::new(0);
+
+    let u = lock.upgradable_read();
+    assert!(lock.try_upgradable_read().is_none());
+    assert!(lock.try_write().is_none());
+
+    // plain readers coexist with the upgradable reader, but block the upgrade
+    let r = lock.try_read().unwrap();
+    let Err(u) = RwLockUpgradableGuard::try_upgrade(u) else {
+        panic!("upgraded while a reader exists");
+    };
+    drop(r);
+
+    let mut w = RwLockUpgradableGuard::try_upgrade(u).ok().unwrap();
+    assert!(lock.try_read().is_none());
+    *w += 1;
+    drop(w);
+
+    let u = lock.upgradable_read();
+    let mut w = RwLockUpgradableGuard::upgrade(u);
+    *w += 1;
+    drop(w);
+
+    assert_eq!(*lock.read(), 2);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rwlock_downgrade() {
+    use awkernel_sync::rwlock::{RwLock, RwLockWriteGuard};
+
+    let lock = RwLock::new(0);
+
+    let mut w = lock.write();
+    *w += 1;
+    let r = RwLockWriteGuard::downgrade(w);
+    assert_eq!(*r, 1);
+
+    // other readers can enter, but writers cannot
+    assert!(lock.try_read().is_some());
+    assert!(lock.try_write().is_none());
+    drop(r);
+
+    assert!(lock.try_write().is_some());
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rwlock_map() {
+    use awkernel_sync::rwlock::{
+        MappedRwLockReadGuard, MappedRwLockWriteGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    };
+
+    let lock = RwLock::new((0, vec![1]));
+
+    // mapped readers still share the lock with other readers
+    let first = RwLockReadGuard::map(lock.read(), |data| &data.1);
+    let second = lock.try_read().unwrap();
+    assert!(lock.try_write().is_none());
+    assert_eq!(first[0], second.1[0]);
+    drop(second);
+
+    let first = MappedRwLockReadGuard::filter_map(first, |data| data.get(1))
+        .err()
+        .unwrap();
+    let (first, ()) = MappedRwLockReadGuard::try_map(first, |data| data.get(1).ok_or(()))
+        .err()
+        .unwrap();
+    assert!(lock.try_write().is_none());
+    drop(first);
+
+    let guard = RwLockReadGuard::filter_map(lock.read(), |data| data.1.get(1))
+        .err()
+        .unwrap();
+    assert!(lock.try_write().is_none());
+    drop(guard);
+
+    // the original writer guard is returned and still excludes readers
+    let guard = RwLockWriteGuard::filter_map(lock.write(), |data| data.1.get_mut(1))
+        .err()
+        .unwrap();
+    assert!(lock.try_read().is_none());
+    drop(guard);
+
+    let (guard, e) = RwLockWriteGuard::try_map(lock.write(), |data| data.1.get_mut(1).ok_or(1))
+        .err()
+        .unwrap();
+    assert_eq!(e, 1);
+    drop(guard);
+
+    let guard = RwLockWriteGuard::map(lock.write(), |data| &mut data.1);
+    let guard = MappedRwLockWriteGuard::filter_map(guard, |data| data.get_mut(1))
+        .err()
+        .unwrap();
+    let (guard, ()) = MappedRwLockWriteGuard::try_map(guard, |data| data.get_mut(1).ok_or(()))
+        .err()
+        .unwrap();
+    let mut guard = MappedRwLockWriteGuard::map(guard, |data| &mut data[0]);
+    *guard += 1;
+    assert!(lock.try_read().is_none());
+    drop(guard);
+
+    assert_eq!(*lock.try_write().unwrap(), (0, vec![2]));
+}
+
+#[cfg(not(loom))]
+fn check_policy<P: awkernel_sync::rwlock::RawRwLockSend + 'static>() {
+    use awkernel_sync::{
+        mcs::MCSLock,
+        rwlock::{RwLock, RwLockWriteGuard},
+    };
+    use std::{
+        sync::{mpsc, Arc},
+        thread,
+    };
+
+    let lock = Arc::new(RwLock::<(usize, usize), P>::with_policy((0, 0)));
+    let num_threads = 4;
+    let num_iterations = 500;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|i| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                for _ in 0..num_iterations {
+                    if i % 2 == 0 {
+                        let mut w = lock.write();
+                        w.0 += 1;
+                        w.1 += 1;
+                    } else {
+                        let r = lock.read();
+                        assert_eq!(r.0, r.1);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let w = lock.try_write().unwrap();
+    assert!(lock.try_read().is_none());
+    assert!(lock.read_timeout(0).is_none());
+    let r = RwLockWriteGuard::downgrade(w);
+    assert!(lock.try_write().is_none());
+    assert!(lock.write_until(|| true).is_none());
+    assert_eq!(r.0, num_threads / 2 * num_iterations);
+    assert!(lock.try_read().is_some());
+    drop(r);
+
+    assert!(lock.try_write().is_some());
+
+    // writer guards are dropped on another thread while this thread uses the per-CPU node pool
+    let (tx, rx) = mpsc::channel();
+    let dropper = thread::spawn(move || {
+        for guard in rx {
+            drop(guard);
+        }
+    });
+
+    let pooled = MCSLock::new(());
+    for _ in 0..1000 {
+        tx.send(lock.write_arc()).unwrap();
+        drop(pooled.lock_pooled());
+    }
+    drop(tx);
+    dropper.join().unwrap();
+
+    // no node of this thread is leaked
+    let locks: Vec<_> = (0..8).map(|_| MCSLock::new(())).collect();
+    let _guards: Vec<_> = locks.iter().map(|lock| lock.lock_pooled()).collect();
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rwlock_policies() {
+    use awkernel_sync::rwlock::{
+        DefaultPolicy, PhaseFairQueue, PhaseFairTicket, ReaderPreferring, WriterPreferring,
+    };
+
+    check_policy::<DefaultPolicy>();
+    check_policy::<WriterPreferring>();
+    check_policy::<ReaderPreferring>();
+    check_policy::<PhaseFairTicket>();
+    check_policy::<PhaseFairQueue>();
+}
+
+#[cfg(not(loom))]
+#[test]
+fn rwlock_with_read_and_write() {
+    use awkernel_sync::rwlock::RwLock;
+
+    let lock = RwLock::new(vec![1]);
+    lock.with_write(|data| data.push(2));
+
+    let reader = lock.read();
+    assert_eq!(lock.with_read(|data| data.len()), 2);
+    assert_eq!(reader.len(), 2);
+}
@@ -0,0 +1,92 @@
+#[cfg(not(loom))]
+#[test]
+fn semaphore() {
+    use awkernel_sync::semaphore::Semaphore;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    let semaphore = Arc::new(Semaphore::new(2));
+    let holders = Arc::new(AtomicUsize::new(0));
+    let num_threads = 4;
+    let num_iterations = 1000;
+
+    let threads: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let semaphore = semaphore.clone();
+            let holders = holders.clone();
+            thread::spawn(move || {
+                for _ in 0..num_iterations {
+                    let _permit = semaphore.acquire();
+                    assert!(holders.fetch_add(1, Ordering::Relaxed) < 2);
+                    holders.fetch_sub(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(semaphore.available_permits(), 2);
+
+    // permits given up are returned by release
+    semaphore.acquire_many(2).forget();
+    assert!(semaphore.try_acquire().is_none());
+    semaphore.release();
+    semaphore.release();
+    assert_eq!(semaphore.try_acquire_many(2).unwrap().num_permits(), 2);
+}
+
+#[cfg(not(loom))]
+#[test]
+fn semaphore_fifo() {
+    use awkernel_sync::semaphore::Semaphore;
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    let semaphore = Arc::new(Semaphore::new(3));
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let permit = semaphore.acquire_many(2);
+
+    // the waiter for 3 permits is not overtaken by the waiters for 1 permit
+    let threads: Vec<_> = [3, 1, 1]
+        .into_iter()
+        .enumerate()
+        .map(|(i, n)| {
+            let waiter = semaphore.clone();
+            let order = order.clone();
+            let thread = thread::spawn(move || {
+                let _permits = waiter.acquire_many(n);
+                order.lock().unwrap().push(i);
+            });
+
+            // let the thread take its ticket before the next one
+            while semaphore.num_waiters() <= i {
+                thread::sleep(Duration::from_millis(1));
+            }
+            thread
+        })
+        .collect();
+
+    assert_eq!(semaphore.num_waiters(), 3);
+    assert_eq!(semaphore.available_permits(), 1);
+    assert!(semaphore.try_acquire().is_none());
+    assert!(order.lock().unwrap().is_empty());
+    drop(permit);
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(order.lock().unwrap()[0], 0);
+    assert_eq!(semaphore.available_permits(), 3);
+}
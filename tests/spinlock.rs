@@ -0,0 +1,38 @@
+#[cfg(not(loom))]
+#[test]
+fn spinlock_map() {
+    use awkernel_sync::spinlock::{MappedSpinLockGuard, SpinLock, SpinLockGuard};
+
+    let lock = SpinLock::new((0, vec![1]));
+
+    let mut guard = SpinLockGuard::map(lock.lock(), |data| &mut data.0);
+    *guard += 1;
+    assert!(lock.try_lock().is_none());
+    drop(guard);
+
+    // the original guard is returned and still holds the lock
+    let guard = SpinLockGuard::filter_map(lock.lock(), |data| data.1.get_mut(1))
+        .err()
+        .unwrap();
+    assert!(lock.try_lock().is_none());
+    drop(guard);
+
+    let (guard, e) = SpinLockGuard::try_map(lock.lock(), |data| data.1.get_mut(1).ok_or(1))
+        .err()
+        .unwrap();
+    assert_eq!(e, 1);
+    drop(guard);
+
+    let guard = SpinLockGuard::map(lock.lock(), |data| &mut data.1);
+    let guard = MappedSpinLockGuard::filter_map(guard, |data| data.get_mut(1))
+        .err()
+        .unwrap();
+    let (guard, ()) = MappedSpinLockGuard::try_map(guard, |data| data.get_mut(1).ok_or(()))
+        .err()
+        .unwrap();
+    let mut guard = MappedSpinLockGuard::map(guard, |data| &mut data[0]);
+    *guard += 1;
+    drop(guard);
+
+    assert_eq!(*lock.try_lock().unwrap(), (1, vec![2]));
+}
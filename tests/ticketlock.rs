@@ -0,0 +1,36 @@
+#[cfg(not(loom))]
+#[test]
+fn ticketlock() {
+    use awkernel_sync::ticket::TicketLock;
+    use std::{sync::Arc, thread, time::Duration};
+
+    let lock = Arc::new(TicketLock::new(Vec::new()));
+    let guard = lock.lock();
+    assert!(!lock.is_contended());
+
+    // waiters are served in the order of their tickets
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let waiter = lock.clone();
+            let thread = thread::spawn(move || waiter.lock().push(i));
+
+            // let the thread take its ticket before the next one
+            while lock.num_waiters() <= i {
+                thread::sleep(Duration::from_millis(1));
+            }
+            thread
+        })
+        .collect();
+
+    assert!(lock.is_contended());
+    assert_eq!(lock.num_waiters(), 4);
+    assert!(lock.try_lock().is_none());
+    drop(guard);
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(*lock.try_lock().unwrap(), [0, 1, 2, 3]);
+    assert!(!lock.is_contended());
+}